@@ -15,19 +15,108 @@
 }
 #endif
 
+struct ProvinceHighlight {
+    hovered_color: vec4<f32>,
+    selected_color: vec4<f32>,
+    hovered_province: u32,
+    selected_province: u32,
+    outline_width: f32,
+}
+
+const NO_PROVINCE: u32 = 0xffffffffu;
+
+const MAP_MODE_TERRAIN: u32 = 0u;
+const MAP_MODE_POLITICAL: u32 = 1u;
+const MAP_MODE_PROVINCE_ID: u32 = 2u;
+
 @group(1) @binding(100)
 var border_texture: texture_2d<f32>;
 @group(1) @binding(101)
 var border_sampler: sampler;
+@group(1) @binding(102)
+var province_id_texture: texture_2d<u32>;
+@group(1) @binding(103)
+var<uniform> highlight: ProvinceHighlight;
+@group(1) @binding(104)
+var<uniform> map_mode: u32;
+@group(1) @binding(105)
+var<storage> owner_colors: array<vec4<f32>>;
+
+fn province_at(texel: vec2<i32>) -> u32 {
+    let size = vec2<i32>(textureDimensions(province_id_texture));
+    return textureLoad(province_id_texture, clamp(texel, vec2<i32>(0), size - 1), 0).r;
+}
+
+// How close a texel is to the edge of the selected province, 1.0 on the edge fading to 0.0
+// at `outline_width` texels away on either side of it
+fn selected_outline(texel: vec2<i32>) -> f32 {
+    let inside = province_at(texel) == highlight.selected_province;
+    let max_radius = i32(ceil(highlight.outline_width));
+    for (var radius = 1; radius <= max_radius; radius++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            for (var dy = -1; dy <= 1; dy++) {
+                let neighbor = texel + vec2<i32>(dx, dy) * radius;
+                if (province_at(neighbor) == highlight.selected_province) != inside {
+                    return 1.0 - f32(radius - 1) / highlight.outline_width;
+                }
+            }
+        }
+    }
+    return 0.0;
+}
+
+// Spreads neighbouring ids over very different colors so adjacent provinces stand apart
+fn province_id_color(province: u32) -> vec3<f32> {
+    var hash = province * 747796405u + 2891336453u;
+    hash = ((hash >> ((hash >> 28u) + 4u)) ^ hash) * 277803737u;
+    hash = (hash >> 22u) ^ hash;
+    return vec3<f32>(
+        f32(hash & 0xffu),
+        f32((hash >> 8u) & 0xffu),
+        f32((hash >> 16u) & 0xffu),
+    ) / 255.0;
+}
+
+fn owner_color(province: u32) -> vec4<f32> {
+    if province >= arrayLength(&owner_colors) {
+        return vec4<f32>(0.0);
+    }
+    return owner_colors[province];
+}
 
 @fragment
 fn fragment(
     in: VertexOutput,
     @builtin(front_facing) is_front: bool,
-) -> @location(0) vec4<f32> {
+) -> FragmentOutput {
     var pbr_input = pbr_input_from_standard_material(in, is_front);
+    let texel = vec2<i32>(in.uv * vec2<f32>(textureDimensions(province_id_texture)));
+    let province = province_at(texel);
+
+    var surface_color: vec4<f32> = pbr_input.material.base_color;
+    if map_mode == MAP_MODE_POLITICAL {
+        let owner = owner_color(province);
+        surface_color = vec4<f32>(mix(surface_color.rgb, owner.rgb, owner.a * 0.75), surface_color.a);
+    } else if map_mode == MAP_MODE_PROVINCE_ID {
+        surface_color = vec4<f32>(province_id_color(province), surface_color.a);
+    }
+
     let border_color: vec4<f32> = textureSample(border_texture, border_sampler, in.uv);
-    let blended_color: vec4<f32> = mix(pbr_input.material.base_color, border_color, border_color.a);
+    var blended_color: vec4<f32> = mix(surface_color, border_color, border_color.a);
+    if highlight.hovered_province != NO_PROVINCE && province == highlight.hovered_province {
+        blended_color = vec4<f32>(
+            mix(blended_color.rgb, highlight.hovered_color.rgb, highlight.hovered_color.a),
+            blended_color.a,
+        );
+    }
+    if highlight.selected_province != NO_PROVINCE {
+        let glow = selected_outline(texel) * highlight.selected_color.a;
+        blended_color = vec4<f32>(
+            mix(blended_color.rgb, highlight.selected_color.rgb, glow) + highlight.selected_color.rgb * glow * 0.5,
+            blended_color.a,
+        );
+    }
+
     pbr_input.material.base_color = blended_color;
 #ifdef PREPASS_PIPELINE
     let out = deferred_output(in, pbr_input);
@@ -36,6 +125,5 @@ fn fragment(
     out.color = apply_pbr_lighting(pbr_input);
     out.color = main_pass_post_lighting_processing(pbr_input, out.color);
 #endif
-    return blended_color;
+    return out;
 }
-
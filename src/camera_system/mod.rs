@@ -3,7 +3,7 @@ mod mouse;
 use bevy::prelude::*;
 pub use mouse::{orbit_mouse, MousePlugin};
 
-use crate::loading_screen;
+use crate::{config_parser, loading_screen};
 
 pub struct ThirdPersonCameraPlugin;
 
@@ -11,9 +11,12 @@ impl Plugin for ThirdPersonCameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(MousePlugin).add_systems(
             Update,
-            (sync_player_camera
-                .after(orbit_mouse)
-                .run_if(in_state(loading_screen::AppState::InGame)),),
+            (
+                apply_camera_config,
+                sync_player_camera
+                    .after(orbit_mouse)
+                    .run_if(in_state(loading_screen::AppState::InGame)),
+            ),
         );
     }
 }
@@ -29,17 +32,30 @@ pub struct ThirdPersonCamera {
     pub inertia: f32,
 }
 
-impl Default for ThirdPersonCamera {
-    fn default() -> Self {
-        ThirdPersonCamera {
+impl ThirdPersonCamera {
+    pub fn new(config: &config_parser::CameraConfig) -> Self {
+        let mut camera = ThirdPersonCamera {
             focus: Vec3::ZERO,
-            mouse_sensitivity: 2.0,
+            mouse_sensitivity: 0.0,
             mouse_orbit_button: MouseButton::Right,
             zoom_enabled: true,
-            zoom: Zoom::new(1.5, 5.0),
-            zoom_sensitivity: 1.0,
-            inertia: 0.97,
-        }
+            zoom: Zoom::new(config.zoom_min, config.zoom_max),
+            zoom_sensitivity: 0.0,
+            inertia: 0.0,
+        };
+        camera.apply_config(config);
+        return camera;
+    }
+
+    /// Takes over the tunable settings, keeping the current zoom inside the new range.
+    pub fn apply_config(&mut self, config: &config_parser::CameraConfig) {
+        self.mouse_sensitivity = config.mouse_sensitivity;
+        self.zoom_enabled = config.zoom_enabled;
+        self.zoom.min = config.zoom_min;
+        self.zoom.max = config.zoom_max;
+        self.zoom.set_radius(self.zoom.radius);
+        self.zoom_sensitivity = config.zoom_sensitivity;
+        self.inertia = config.inertia;
     }
 }
 
@@ -57,11 +73,34 @@ impl Zoom {
             radius: (min + max) / 2.0,
         }
     }
+
+    pub fn radius(&self) -> f32 {
+        return self.radius;
+    }
+
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius.clamp(self.min, self.max);
+    }
 }
 
 #[derive(Component)]
 pub struct ThirdPersonCameraTarget;
 
+fn apply_camera_config(
+    engine_config: Option<Res<config_parser::EngineConfig>>,
+    mut cam_q: Query<&mut ThirdPersonCamera>,
+) {
+    let Some(engine_config) = engine_config else {
+        return;
+    };
+    if !engine_config.is_changed() {
+        return;
+    }
+    for mut cam in cam_q.iter_mut() {
+        cam.apply_config(&engine_config.camera);
+    }
+}
+
 fn sync_player_camera(
     planet_q: Query<&Transform, With<ThirdPersonCameraTarget>>,
     mut cam_q: Query<(&mut ThirdPersonCamera, &mut Transform), Without<ThirdPersonCameraTarget>>,
@@ -9,10 +9,7 @@ use bevy::{
 use bevy_mod_raycast::prelude::*;
 
 use crate::camera_system::ThirdPersonCamera;
-use crate::config_parser;
-use crate::{camera_system, loading_screen::AppState::InGame, planet};
-
-use crate::planet::MapImage;
+use crate::{camera_system, loading_screen::AppState::InGame};
 
 #[derive(Resource)]
 pub struct CursorOverPlanet(bool);
@@ -33,7 +30,6 @@ impl Plugin for MousePlugin {
                     zoom_mouse
                         .run_if(in_state(InGame))
                         .run_if(camera_system::zoom_condition),
-                    planet_province_coordinates.run_if(in_state(InGame)),
                 )
                     .chain(),
             );
@@ -62,49 +58,6 @@ fn ray_cast_planet(
     }
 }
 
-fn planet_province_coordinates(
-    cursor_ray: Res<CursorRay>,
-    mut raycast: Raycast,
-    planet_q: Query<&Transform, With<camera_system::ThirdPersonCameraTarget>>,
-    provinces_query: Query<&planet::Province>,
-    map_image_query: Res<MapImage>,
-    engine_config: Res<config_parser::EngineConfig>,
-) {
-    if let Some(cursor_ray) = **cursor_ray {
-        let val: &[(Entity, IntersectionData)] = raycast.cast_ray(cursor_ray, &default());
-        for (entity, intersection_data) in val.iter() {
-            if planet_q.get(*entity).is_ok() {
-                let intersection_point = intersection_data.position();
-                let local_intersection_point = planet_q.get(*entity).unwrap().compute_matrix()
-                    * intersection_point.extend(1.0);
-
-                let theta = local_intersection_point
-                    .x
-                    .atan2(-local_intersection_point.z);
-                let phi = local_intersection_point.y.asin();
-
-                let u_unwrapped = ((theta + PI) / (2.0 * PI)) + 0.5;
-                let u = 1.0 - (u_unwrapped % 1.0);
-                let v = 1.0 - ((phi + std::f32::consts::FRAC_PI_2) / PI);
-
-                let texture_x: u32 = (u * engine_config.map_dimensions as f32) as u32;
-                let texture_y: u32 = (v * engine_config.map_dimensions as f32) as u32;
-
-                let r = map_image_query.image.get_pixel(texture_x, texture_y).0[0];
-                let g = map_image_query.image.get_pixel(texture_x, texture_y).0[1];
-                let b = map_image_query.image.get_pixel(texture_x, texture_y).0[2];
-
-                for province in provinces_query.iter() {
-                    if province.color == [r, g, b] {
-                        //println!("Found Province with ID: {}", province.id);
-                        break;
-                    }
-                }
-            }
-        }
-    }
-}
-
 fn orbit_condition(
     cam: &ThirdPersonCamera,
     mouse: &Res<Input<MouseButton>>,
@@ -171,47 +124,17 @@ pub fn orbit_mouse(
         cam.focus + rot_matrix.mul_vec3(Vec3::new(0.0, 0.0, cam.zoom.radius));
 }
 
-fn zoom_mouse(
-    mut scroll_evr: EventReader<MouseWheel>,
-    mut cam_q: Query<&mut ThirdPersonCamera>,
-    mut commands: Commands,
-    cam_transform_q: Query<&Transform, With<ThirdPersonCamera>>,
-    query: Query<(Entity, &Transform, &planet::PlanetEntity)>,
-    planet_lods: Res<planet::PlanetLODs>,
-) {
+fn zoom_mouse(mut scroll_evr: EventReader<MouseWheel>, mut cam_q: Query<&mut ThirdPersonCamera>) {
     let mut scroll: f32 = 0.0;
     for ev in scroll_evr.read() {
         scroll += ev.y;
     }
 
-    if let (Ok(mut cam), Ok(camera_transform)) =
-        (cam_q.get_single_mut(), cam_transform_q.get_single())
-    {
+    if let Ok(mut cam) = cam_q.get_single_mut() {
         if scroll.abs() > 0.0 {
             let new_radius: f32 =
                 cam.zoom.radius - scroll * cam.zoom.radius * 0.1 * cam.zoom_sensitivity;
             cam.zoom.radius = new_radius.clamp(cam.zoom.min, cam.zoom.max);
         }
-
-        for (entity, transform, planet_mesh) in query.iter() {
-            let distance = transform.translation.distance(camera_transform.translation);
-            if !planet_lods.level_of_detail_meshes[0].2.is_empty() {
-                let num_lods = planet_lods.level_of_detail_meshes[0].2.len();
-                let lod_index = ((distance - cam.zoom.min) / (cam.zoom.max - cam.zoom.min)
-                    * (num_lods as f32))
-                    .clamp(0.0, (num_lods - 1) as f32) as usize;
-                let reversed_lod_index = num_lods - 1 - lod_index;
-                let mut handle: Option<Handle<Mesh>> = None;
-                for (_direction, suffix_dir, lods) in planet_lods.level_of_detail_meshes.iter() {
-                    if suffix_dir == &planet_mesh.direction {
-                        handle = Some(lods[reversed_lod_index].clone());
-                        break;
-                    }
-                }
-                if let Some(pulled_handle) = handle {
-                    commands.entity(entity).insert(pulled_handle);
-                }
-            }
-        }
     }
 }
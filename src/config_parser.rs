@@ -1,19 +1,961 @@
-use bevy::prelude::*;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy::{
+    asset::{
+        io::{AssetSource, Reader},
+        AssetLoader, AssetPath, LoadContext, LoadState,
+    },
+    prelude::*,
+    utils::BoxedFuture,
+};
+use futures_lite::AsyncReadExt;
 
 use serde::{Deserialize, Serialize};
 use serde_yaml::{self};
 
-#[derive(Debug, Serialize, Deserialize, Resource)]
+use crate::loading_screen::AppState;
+
+pub const DEFAULT_CONFIG_PATH: &str = "assets/configs/engine.yml";
+pub const CONFIG_ENV_VAR: &str = "RED_SAND_CONFIG";
+
+/// Asset source rooted at the directory holding the engine config, so the config can live
+/// outside of `assets/` and still be loaded and reloaded through the asset server.
+const CONFIG_ASSET_SOURCE: &str = "config";
+
+/// Deeper quadtrees would overflow the integer vertex coordinates of a chunk.
+const MAX_LOD_DEPTH: u32 = 16;
+/// Largest face texture the procedural terrain generator will make.
+const MAX_PROCEDURAL_FACE_SIZE: u32 = 8192;
+/// Largest province map face side whose pixel count still fits in a `u32`.
+const MAX_MAP_DIMENSIONS: u32 = u16::MAX as u32;
+/// Every province needs its own color with no black channel, and only 255^3 of those exist.
+const MAX_PROVINCES: u32 = 255 * 255 * 255;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Resource, Asset, TypePath)]
+#[serde(default)]
 pub struct EngineConfig {
-    pub planet_lods: Vec<u32>,
     pub map_dimensions: u32,
     pub num_provinces: u32,
+    pub seed: u64,
+    pub terrain: TerrainConfig,
+    pub lod: LodConfig,
+    pub provinces: ProvincesConfig,
+    pub noise: NoiseConfig,
+    pub camera: CameraConfig,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            map_dimensions: 300,
+            num_provinces: 50,
+            seed: 1337,
+            terrain: TerrainConfig::default(),
+            lod: LodConfig::default(),
+            provinces: ProvincesConfig::default(),
+            noise: NoiseConfig::default(),
+            camera: CameraConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainConfig {
+    /// How far the highest height map value pushes the surface out, relative to the planet radius.
+    pub height_map_scale: f32,
+    /// How heights are filtered between height map texels.
+    pub height_sampling: HeightSampling,
+    /// Where the per face normal maps come from.
+    pub normal_maps: NormalMapSource,
+    /// Whether the planet uses the authored textures or generates its own.
+    pub source: TerrainSource,
+    pub procedural: ProceduralTerrainConfig,
+    /// Impact craters stamped onto the height maps of either source.
+    pub craters: CraterConfig,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        TerrainConfig {
+            height_map_scale: 0.25,
+            height_sampling: HeightSampling::default(),
+            normal_maps: NormalMapSource::default(),
+            source: TerrainSource::default(),
+            procedural: ProceduralTerrainConfig::default(),
+            craters: CraterConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TerrainSource {
+    /// The color and height cube faces in `textures/mars`.
+    #[default]
+    Textures,
+    /// Height and color faces generated from the world seed, normal maps are always generated.
+    Procedural,
+}
+
+/// Layers of the procedural terrain. Noise grid sizes are measured on a sphere of
+/// `TERRAIN_NOISE_RADIUS` cells from the planet terrain module, so they mean the same at
+/// every face size.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProceduralTerrainConfig {
+    /// Width and height of every generated face texture.
+    pub face_size: u32,
+    /// Large basins and plateaus.
+    pub continents: NoiseConfig,
+    /// Pushes plateaus south like the Martian dichotomy, 0 spreads them evenly.
+    pub hemisphere_bias: f32,
+    /// Mountain ranges, mostly on the plateaus.
+    pub highlands: NoiseConfig,
+    /// Cells of this noise whose value drops towards -1 become crater bowls.
+    pub crater_fields: NoiseConfig,
+    /// Height the deepest crater bowls cut out of the terrain, which spans 0 to 1.
+    pub crater_depth: f32,
+    /// Latitude in degrees beyond which the polar ice caps begin.
+    pub polar_cap_latitude: f32,
+}
+
+impl Default for ProceduralTerrainConfig {
+    fn default() -> Self {
+        ProceduralTerrainConfig {
+            face_size: 512,
+            continents: NoiseConfig {
+                kind: NoiseKind::Perlin,
+                grid_size: 200.0,
+                octaves: 5,
+                lacunarity: 2.0,
+                gain: 0.5,
+                strength: 1.5,
+                warp_strength: 1.0,
+            },
+            hemisphere_bias: 0.35,
+            highlands: NoiseConfig {
+                kind: NoiseKind::Ridged,
+                grid_size: 50.0,
+                octaves: 5,
+                lacunarity: 2.0,
+                gain: 0.5,
+                strength: 1.0,
+                warp_strength: 1.0,
+            },
+            crater_fields: NoiseConfig {
+                kind: NoiseKind::Worley,
+                grid_size: 16.0,
+                octaves: 2,
+                lacunarity: 2.5,
+                gain: 0.5,
+                strength: 1.0,
+                warp_strength: 1.0,
+            },
+            crater_depth: 0.12,
+            polar_cap_latitude: 75.0,
+        }
+    }
+}
+
+/// Craters scattered over the planet from the world seed. Radii follow a power law, so small
+/// craters far outnumber large ones.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CraterConfig {
+    /// How many craters are stamped, 0 leaves the height maps untouched.
+    pub count: u32,
+    /// Smallest crater radius in degrees of arc.
+    pub min_radius: f32,
+    /// Largest crater radius in degrees of arc.
+    pub max_radius: f32,
+    /// Exponent of the cumulative size-frequency distribution, the number of craters wider
+    /// than r falls off as r^-size_exponent.
+    pub size_exponent: f32,
+    /// Bowl depth of the largest crater in height map units, smaller craters scale down with
+    /// their radius.
+    pub max_depth: f32,
+    /// Height of the raised rim as a fraction of the bowl depth.
+    pub rim_height: f32,
+    /// Craters wider than this many degrees get a central peak.
+    pub central_peak_radius: f32,
+    /// How far the ejecta blanket reaches from the centre, in crater radii.
+    pub ejecta_extent: f32,
+}
+
+impl Default for CraterConfig {
+    fn default() -> Self {
+        CraterConfig {
+            count: 0,
+            min_radius: 0.5,
+            max_radius: 12.0,
+            size_exponent: 2.0,
+            max_depth: 0.15,
+            rim_height: 0.3,
+            central_peak_radius: 4.0,
+            ejecta_extent: 2.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeightSampling {
+    /// The closest texel, shows the texel grid as terraces.
+    Nearest,
+    /// Linear blend of the four surrounding texels.
+    #[default]
+    Bilinear,
+    /// Catmull-Rom spline through the sixteen surrounding texels, smooth slopes as well.
+    Bicubic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NormalMapSource {
+    /// Derived from the height maps while loading, so they always match the terrain.
+    #[default]
+    Generated,
+    /// The hand-made textures in `textures/mars/normal`.
+    Files,
+}
+
+/// How each cube face is split into a quadtree of chunks that refine near the camera.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LodConfig {
+    /// Vertices along each side of a chunk, at every depth of the quadtree.
+    pub chunk_resolution: u32,
+    /// How many times a face may be subdivided.
+    pub max_depth: u32,
+    /// A chunk splits once the camera is closer than this many chunk widths.
+    pub split_distance: f32,
+    /// Extra fraction of `split_distance` the camera has to back off before split chunks
+    /// merge again, so hovering around the threshold does not pop between levels.
+    pub merge_hysteresis: f32,
+    /// Depth of the skirts hiding cracks between chunks of different depths, in chunk widths.
+    pub skirt_depth: f32,
+}
+
+impl Default for LodConfig {
+    fn default() -> Self {
+        LodConfig {
+            chunk_resolution: 33,
+            max_depth: 6,
+            split_distance: 2.0,
+            merge_hysteresis: 0.25,
+            skirt_depth: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProvincesConfig {
+    /// How far, in map cells, noise bends province borders away from straight Voronoi edges.
+    pub displacement_factor: f64,
+    /// How distances from the province seeds are measured.
+    pub boundaries: BoundaryMode,
+    /// In terrain mode, the extra distance charged per unit of height climbed or descended,
+    /// both measured in planet radii.
+    pub slope_cost: f64,
+}
+
+impl Default for ProvincesConfig {
+    fn default() -> Self {
+        ProvincesConfig {
+            displacement_factor: 84.0,
+            boundaries: BoundaryMode::default(),
+            slope_cost: 20.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoundaryMode {
+    /// Straight Voronoi distance with noise displacing the sample points.
+    #[default]
+    Noise,
+    /// Distance across the surface that grows with every climb, so borders settle on ridges,
+    /// canyon walls and crater rims. The noise only roughens the borders on flat ground.
+    Terrain,
+}
+
+/// Fractal noise settings, each octave multiplies the frequency by `lacunarity` and the
+/// amplitude by `gain`, and the summed value is scaled by `strength` before clamping.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseConfig {
+    pub kind: NoiseKind,
+    /// Map cells per noise lattice cell at the first octave.
+    pub grid_size: f32,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub gain: f32,
+    pub strength: f32,
+    /// How far `domain_warped` noise pushes sample points around, in lattice cells.
+    pub warp_strength: f32,
+}
+
+impl Default for NoiseConfig {
+    fn default() -> Self {
+        NoiseConfig {
+            kind: NoiseKind::default(),
+            grid_size: 400.0,
+            octaves: 8,
+            lacunarity: 2.0,
+            gain: 0.5,
+            strength: 1.2,
+            warp_strength: 1.0,
+        }
+    }
+}
+
+impl NoiseConfig {
+    /// Adds the problems of a noise section, named `section` in the messages, to `errors`.
+    fn validate(&self, section: &str, errors: &mut Vec<String>) {
+        if self.grid_size.is_nan() || self.grid_size <= 0.0 {
+            errors.push(format!(
+                "{}.grid_size is {} but must be greater than 0",
+                section, self.grid_size
+            ));
+        }
+        if self.octaves == 0 {
+            errors.push(format!("{}.octaves needs at least one octave", section));
+        }
+        if ![
+            self.lacunarity,
+            self.gain,
+            self.strength,
+            self.warp_strength,
+        ]
+        .iter()
+        .all(|value| value.is_finite())
+        {
+            errors.push(format!(
+                "{}.lacunarity, gain, strength and warp_strength must be numbers",
+                section
+            ));
+        }
+    }
+}
+
+/// The kind of noise a `NoiseConfig` builds, all but `worley` are summed over the octaves as
+/// fractal Brownian motion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseKind {
+    /// Classic gradient noise on a cubic lattice.
+    #[default]
+    Perlin,
+    /// Gradient noise on a body-centred cubic lattice, without Perlin's axis aligned streaks.
+    OpenSimplex,
+    /// Cellular noise from the distance to the closest random feature point, octaves included.
+    Worley,
+    /// Sharp crests where the Perlin noise crosses zero, detail piles up on the ridges.
+    Ridged,
+    /// Perlin fBm sampled at points displaced by more fBm, giving swirling, folded shapes.
+    DomainWarped,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    pub mouse_sensitivity: f32,
+    pub zoom_enabled: bool,
+    pub zoom_min: f32,
+    pub zoom_max: f32,
+    pub zoom_sensitivity: f32,
+    pub inertia: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            mouse_sensitivity: 2.0,
+            zoom_enabled: true,
+            zoom_min: 1.5,
+            zoom_max: 5.0,
+            zoom_sensitivity: 1.0,
+            inertia: 0.97,
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Whether switching to `other` changes the province maps, not just the planet meshes.
+    pub fn changes_maps(&self, other: &EngineConfig) -> bool {
+        return self.map_dimensions != other.map_dimensions
+            || self.num_provinces != other.num_provinces
+            || self.seed != other.seed
+            || self.provinces != other.provinces
+            || self.noise != other.noise
+            // Procedural terrain is generated along with the maps
+            || self.terrain.source != other.terrain.source
+            || (self.terrain.source == TerrainSource::Procedural
+                && self.terrain.procedural != other.terrain.procedural)
+            || self.terrain.craters != other.terrain.craters
+            // Terrain following borders are measured on the scaled and filtered heights
+            || (self.provinces.boundaries == BoundaryMode::Terrain
+                && (self.terrain.height_map_scale != other.terrain.height_map_scale
+                    || self.terrain.height_sampling != other.terrain.height_sampling));
+    }
+
+    /// Whether switching to `other` changes the planet meshes.
+    pub fn changes_meshes(&self, other: &EngineConfig) -> bool {
+        return self.lod != other.lod || self.terrain != other.terrain;
+    }
+
+    /// Checks every field and collects all problems rather than stopping at the first one.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors: Vec<String> = Vec::new();
+        if self.lod.chunk_resolution < 2 {
+            errors.push(format!(
+                "lod.chunk_resolution is {} but every chunk needs at least 2 vertices per side",
+                self.lod.chunk_resolution
+            ));
+        }
+        if self.lod.max_depth > MAX_LOD_DEPTH {
+            errors.push(format!(
+                "lod.max_depth is {} but can be at most {}",
+                self.lod.max_depth, MAX_LOD_DEPTH
+            ));
+        }
+        if self.lod.split_distance.is_nan() || self.lod.split_distance <= 0.0 {
+            errors.push(format!(
+                "lod.split_distance is {} but must be greater than 0",
+                self.lod.split_distance
+            ));
+        }
+        if self.lod.merge_hysteresis.is_nan() || self.lod.merge_hysteresis < 0.0 {
+            errors.push(format!(
+                "lod.merge_hysteresis is {} but must not be negative",
+                self.lod.merge_hysteresis
+            ));
+        }
+        if self.lod.skirt_depth.is_nan() || self.lod.skirt_depth < 0.0 {
+            errors.push(format!(
+                "lod.skirt_depth is {} but must not be negative",
+                self.lod.skirt_depth
+            ));
+        }
+        if !(2..=MAX_MAP_DIMENSIONS).contains(&self.map_dimensions) {
+            errors.push(format!(
+                "map_dimensions is {} but must be between 2 and {}",
+                self.map_dimensions, MAX_MAP_DIMENSIONS
+            ));
+        } else {
+            // Province seeds are placed strictly inside the map cube, so only
+            // (map_dimensions - 1)^3 distinct positions exist
+            let seed_positions = (self.map_dimensions as u64 - 1).pow(3);
+            if self.num_provinces as u64 > seed_positions {
+                errors.push(format!(
+                    "num_provinces is {} but a map_dimensions of {} only fits {} provinces",
+                    self.num_provinces, self.map_dimensions, seed_positions
+                ));
+            }
+        }
+        if self.num_provinces > MAX_PROVINCES {
+            errors.push(format!(
+                "num_provinces is {} but only {} distinct province colors exist",
+                self.num_provinces, MAX_PROVINCES
+            ));
+        }
+        if !self.terrain.height_map_scale.is_finite() {
+            errors.push("terrain.height_map_scale must be a number".to_owned());
+        }
+        let procedural = &self.terrain.procedural;
+        if !(2..=MAX_PROCEDURAL_FACE_SIZE).contains(&procedural.face_size) {
+            errors.push(format!(
+                "terrain.procedural.face_size is {} but must be between 2 and {}",
+                procedural.face_size, MAX_PROCEDURAL_FACE_SIZE
+            ));
+        }
+        procedural
+            .continents
+            .validate("terrain.procedural.continents", &mut errors);
+        procedural
+            .highlands
+            .validate("terrain.procedural.highlands", &mut errors);
+        procedural
+            .crater_fields
+            .validate("terrain.procedural.crater_fields", &mut errors);
+        if ![
+            procedural.hemisphere_bias,
+            procedural.crater_depth,
+            procedural.polar_cap_latitude,
+        ]
+        .iter()
+        .all(|value| value.is_finite())
+        {
+            errors.push(
+                "terrain.procedural.hemisphere_bias, crater_depth and polar_cap_latitude must be numbers"
+                    .to_owned(),
+            );
+        }
+        let craters = &self.terrain.craters;
+        if !(craters.min_radius > 0.0 && craters.min_radius <= craters.max_radius)
+            || craters.max_radius > 90.0
+        {
+            errors.push(format!(
+                "terrain.craters radii run from {} to {} but must be greater than 0, at most 90 and in order",
+                craters.min_radius, craters.max_radius
+            ));
+        }
+        if craters.size_exponent.is_nan() || craters.size_exponent <= 0.0 {
+            errors.push(format!(
+                "terrain.craters.size_exponent is {} but must be greater than 0",
+                craters.size_exponent
+            ));
+        }
+        if craters.ejecta_extent.is_nan() || craters.ejecta_extent < 1.0 {
+            errors.push(format!(
+                "terrain.craters.ejecta_extent is {} but must be at least 1",
+                craters.ejecta_extent
+            ));
+        }
+        if ![
+            craters.max_depth,
+            craters.rim_height,
+            craters.central_peak_radius,
+        ]
+        .iter()
+        .all(|value| value.is_finite())
+        {
+            errors.push(
+                "terrain.craters.max_depth, rim_height and central_peak_radius must be numbers"
+                    .to_owned(),
+            );
+        }
+        if !self.provinces.displacement_factor.is_finite() {
+            errors.push("provinces.displacement_factor must be a number".to_owned());
+        }
+        if !(self.provinces.slope_cost.is_finite() && self.provinces.slope_cost >= 0.0) {
+            errors.push(format!(
+                "provinces.slope_cost is {} but must not be negative",
+                self.provinces.slope_cost
+            ));
+        }
+        self.noise.validate("noise", &mut errors);
+        if !(self.camera.zoom_min > 0.0 && self.camera.zoom_min <= self.camera.zoom_max) {
+            errors.push(format!(
+                "camera zoom range {} to {} must be positive and zoom_min must not exceed zoom_max",
+                self.camera.zoom_min, self.camera.zoom_max
+            ));
+        }
+        if !(0.0..1.0).contains(&self.camera.inertia) {
+            errors.push(format!(
+                "camera.inertia is {} but must be at least 0 and below 1",
+                self.camera.inertia
+            ));
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+        return Err(errors);
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, serde_yaml::Error),
+    Invalid(PathBuf, Vec<String>),
 }
 
-pub fn read_configs(mut commands: Commands) {
-    let f = std::fs::File::open("assets/configs/engine.yml")
-        .expect("Could not open engine config file.");
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ConfigError::Io(path, error) => {
+                write!(f, "Could not open engine config {:?}: {}", path, error)
+            }
+            ConfigError::Parse(path, error) => {
+                write!(f, "Could not read engine config {:?}: {}", path, error)
+            }
+            ConfigError::Invalid(path, errors) => {
+                write!(f, "Engine config {:?} is invalid:", path)?;
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+                Ok(())
+            }
+        };
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Why the engine config could not be used, shown on the loading screen.
+#[derive(Resource, Debug)]
+pub struct ConfigErrors(pub ConfigError);
+
+/// Picks the config file from `--config <path>`, then `RED_SAND_CONFIG`, then the default.
+pub fn config_path(args: impl IntoIterator<Item = String>, env_path: Option<String>) -> PathBuf {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            if let Some(path) = args.next() {
+                return PathBuf::from(path);
+            }
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            return PathBuf::from(path);
+        }
+    }
+    if let Some(path) = env_path {
+        return PathBuf::from(path);
+    }
+    return PathBuf::from(DEFAULT_CONFIG_PATH);
+}
+
+pub fn load_config(path: &Path) -> Result<EngineConfig, ConfigError> {
+    let f = std::fs::File::open(path).map_err(|error| ConfigError::Io(path.to_owned(), error))?;
     let engine_config: EngineConfig =
-        serde_yaml::from_reader(f).expect("Could not read engine values.");
-    commands.insert_resource(engine_config);
+        serde_yaml::from_reader(f).map_err(|error| ConfigError::Parse(path.to_owned(), error))?;
+    engine_config
+        .validate()
+        .map_err(|errors| ConfigError::Invalid(path.to_owned(), errors))?;
+    return Ok(engine_config);
+}
+
+/// Parses and validates YAML engine configs, failing with the same errors as `load_config`.
+struct EngineConfigLoader;
+
+impl AssetLoader for EngineConfigLoader {
+    type Asset = EngineConfig;
+    type Settings = ();
+    type Error = ConfigError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<EngineConfig, ConfigError>> {
+        return Box::pin(async move {
+            let path = load_context.path().to_owned();
+            let mut bytes = Vec::new();
+            reader
+                .read_to_end(&mut bytes)
+                .await
+                .map_err(|error| ConfigError::Io(path.clone(), error))?;
+            let engine_config: EngineConfig = serde_yaml::from_slice(&bytes)
+                .map_err(|error| ConfigError::Parse(path.clone(), error))?;
+            engine_config
+                .validate()
+                .map_err(|errors| ConfigError::Invalid(path, errors))?;
+            return Ok(engine_config);
+        });
+    }
+
+    fn extensions(&self) -> &[&str] {
+        return &["yml", "yaml"];
+    }
+}
+
+/// The engine config file being watched, `pending` is set once it changed on disk and is
+/// cleared when the change has been applied.
+#[derive(Resource)]
+struct ConfigFile {
+    path: PathBuf,
+    handle: Handle<EngineConfig>,
+    last_modified: Option<SystemTime>,
+    poll_timer: Timer,
+    pending: bool,
+}
+
+/// Loads the engine config as an asset and rebuilds the planet whenever the file changes.
+/// Has to be added before `DefaultPlugins` so its asset source is registered in time.
+pub struct ConfigPlugin {
+    pub path: PathBuf,
+}
+
+impl ConfigPlugin {
+    pub fn from_env() -> Self {
+        return ConfigPlugin {
+            path: config_path(std::env::args().skip(1), std::env::var(CONFIG_ENV_VAR).ok()),
+        };
+    }
+}
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        let directory = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy(),
+            _ => ".".into(),
+        };
+        app.register_asset_source(
+            CONFIG_ASSET_SOURCE,
+            AssetSource::build().with_reader(AssetSource::get_default_reader(directory.into())),
+        )
+        .add_systems(
+            Update,
+            (poll_config_file, apply_config_changes)
+                .chain()
+                .run_if(resource_exists::<ConfigFile>()),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        app.init_asset::<EngineConfig>()
+            .register_asset_loader(EngineConfigLoader);
+
+        let file_name = PathBuf::from(self.path.file_name().unwrap_or_default());
+        let asset_path = AssetPath::from(file_name).with_source(CONFIG_ASSET_SOURCE);
+        let handle = app.world.resource::<AssetServer>().load(asset_path);
+        app.insert_resource(ConfigFile {
+            path: self.path.clone(),
+            handle,
+            last_modified: modified_time(&self.path),
+            poll_timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+            pending: true,
+        });
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    return std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+}
+
+/// Watches the config file's modification time and asks the asset server to reload it.
+fn poll_config_file(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    state: Res<State<AppState>>,
+    mut config_file: ResMut<ConfigFile>,
+) {
+    if !config_file.poll_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let modified = modified_time(&config_file.path);
+    if modified.is_none() || modified == config_file.last_modified {
+        return;
+    }
+    config_file.last_modified = modified;
+    info!("Engine config {:?} changed, reloading", config_file.path);
+    asset_server.reload(config_file.handle.path().unwrap().clone_owned());
+
+    // A broken config never produces an asset event, so refresh the error screen directly
+    if *state.get() == AppState::InvalidConfig {
+        if let Err(error) = load_config(&config_file.path) {
+            error!("{}", error);
+            commands.insert_resource(ConfigErrors(error));
+        }
+    }
+}
+
+/// Moves on from the config states once the config has loaded, and re-enters map or mesh
+/// generation when it changes while in game. Changes made mid generation wait until the
+/// planet is back in game.
+#[allow(clippy::too_many_arguments)]
+fn apply_config_changes(
+    mut commands: Commands,
+    mut config_evr: EventReader<AssetEvent<EngineConfig>>,
+    mut config_file: ResMut<ConfigFile>,
+    engine_configs: Res<Assets<EngineConfig>>,
+    asset_server: Res<AssetServer>,
+    current_config: Option<Res<EngineConfig>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for event in config_evr.read() {
+        if event.is_loaded_with_dependencies(&config_file.handle)
+            || event.is_modified(&config_file.handle)
+        {
+            config_file.pending = true;
+        }
+    }
+
+    match state.get() {
+        AppState::LoadingConfigs => {
+            if asset_server.load_state(&config_file.handle) == LoadState::Failed {
+                // The asset server only logs why, so read the file again for the error screen
+                if let Err(error) = load_config(&config_file.path) {
+                    error!("{}", error);
+                    commands.insert_resource(ConfigErrors(error));
+                    next_state.set(AppState::InvalidConfig);
+                }
+                return;
+            }
+        }
+        AppState::InvalidConfig | AppState::InGame => {}
+        _ => return,
+    }
+
+    if !config_file.pending {
+        return;
+    }
+    let Some(engine_config) = engine_configs.get(&config_file.handle) else {
+        return;
+    };
+    config_file.pending = false;
+
+    match state.get() {
+        AppState::InGame => {
+            let Some(current_config) = current_config else {
+                return;
+            };
+            if engine_config.changes_maps(&current_config) {
+                info!("Engine config changed, regenerating province maps");
+                next_state.set(AppState::GeneratingMaps);
+            } else if engine_config.changes_meshes(&current_config) {
+                info!("Engine config changed, regenerating planet meshes");
+                next_state.set(AppState::GeneratingMeshes);
+            } else if *engine_config == *current_config {
+                return;
+            }
+        }
+        _ => {
+            commands.remove_resource::<ConfigErrors>();
+            next_state.set(AppState::LoadingImageAssets);
+        }
+    }
+    commands.insert_resource(engine_config.clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        return values.iter().map(|value| value.to_string()).collect();
+    }
+
+    #[test]
+    fn config_path_prefers_argument_then_env() {
+        let env = Some("from_env.yml".to_owned());
+        assert_eq!(
+            config_path(args(&["--config", "a.yml"]), env.clone()),
+            PathBuf::from("a.yml")
+        );
+        assert_eq!(
+            config_path(args(&["--config=b.yml"]), env.clone()),
+            PathBuf::from("b.yml")
+        );
+        assert_eq!(config_path(args(&[]), env), PathBuf::from("from_env.yml"));
+        assert_eq!(
+            config_path(args(&[]), None),
+            PathBuf::from(DEFAULT_CONFIG_PATH)
+        );
+    }
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let engine_config: EngineConfig = serde_yaml::from_str("num_provinces: 12").unwrap();
+        assert_eq!(engine_config.num_provinces, 12);
+        assert_eq!(engine_config.lod, EngineConfig::default().lod);
+        assert!(engine_config.validate().is_ok());
+    }
+
+    #[test]
+    fn sections_fill_in_missing_fields() {
+        let engine_config: EngineConfig =
+            serde_yaml::from_str("noise:\n  octaves: 4\ncamera:\n  zoom_max: 8.0").unwrap();
+        assert_eq!(engine_config.noise.octaves, 4);
+        assert_eq!(
+            engine_config.noise.grid_size,
+            NoiseConfig::default().grid_size
+        );
+        assert_eq!(engine_config.camera.zoom_max, 8.0);
+
+        let ridged: EngineConfig =
+            serde_yaml::from_str("noise: { kind: ridged, octaves: 6, lacunarity: 2.0, gain: 0.5 }")
+                .unwrap();
+        assert_eq!(ridged.noise.kind, NoiseKind::Ridged);
+        assert_eq!(ridged.noise.octaves, 6);
+        assert_eq!(
+            engine_config.camera.zoom_min,
+            CameraConfig::default().zoom_min
+        );
+        assert_eq!(engine_config.terrain, TerrainConfig::default());
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let engine_config = EngineConfig {
+            lod: LodConfig {
+                chunk_resolution: 1,
+                ..default()
+            },
+            map_dimensions: 3,
+            num_provinces: 9,
+            seed: 0,
+            ..default()
+        };
+        let errors = engine_config.validate().unwrap_err();
+        assert_eq!(errors.len(), 2);
+
+        let broken_lods = EngineConfig {
+            lod: LodConfig {
+                chunk_resolution: 0,
+                max_depth: MAX_LOD_DEPTH + 1,
+                split_distance: 0.0,
+                merge_hysteresis: -0.5,
+                skirt_depth: -1.0,
+            },
+            map_dimensions: 1,
+            ..default()
+        };
+        assert_eq!(broken_lods.validate().unwrap_err().len(), 6);
+
+        let too_many_provinces = EngineConfig {
+            map_dimensions: 1000,
+            num_provinces: MAX_PROVINCES + 1,
+            ..default()
+        };
+        assert_eq!(too_many_provinces.validate().unwrap_err().len(), 1);
+        let overflowing_map = EngineConfig {
+            map_dimensions: MAX_MAP_DIMENSIONS + 1,
+            ..default()
+        };
+        assert_eq!(overflowing_map.validate().unwrap_err().len(), 1);
+        let largest_map = EngineConfig {
+            map_dimensions: MAX_MAP_DIMENSIONS,
+            ..default()
+        };
+        assert!(largest_map.validate().is_ok());
+    }
+
+    #[test]
+    fn procedural_terrain_changes_regenerate_maps() {
+        let textures = EngineConfig::default();
+        let mut tweaked = textures.clone();
+        tweaked.terrain.procedural.crater_depth = 0.3;
+        // Unused procedural settings only touch the meshes
+        assert!(!textures.changes_maps(&tweaked));
+
+        let procedural = EngineConfig {
+            terrain: TerrainConfig {
+                source: TerrainSource::Procedural,
+                ..default()
+            },
+            ..default()
+        };
+        assert!(textures.changes_maps(&procedural));
+        let mut procedural_tweaked = procedural.clone();
+        procedural_tweaked.terrain.procedural.crater_depth = 0.3;
+        assert!(procedural.changes_maps(&procedural_tweaked));
+
+        // Craters are stamped onto the heights of either source
+        let mut cratered = textures.clone();
+        cratered.terrain.craters.count = 100;
+        assert!(textures.changes_maps(&cratered));
+
+        // Height scaling only moves borders that follow the terrain
+        let mut rescaled = textures.clone();
+        rescaled.terrain.height_map_scale = 0.5;
+        assert!(!textures.changes_maps(&rescaled));
+        let mut terrain_borders = textures.clone();
+        terrain_borders.provinces.boundaries = BoundaryMode::Terrain;
+        let mut terrain_rescaled = terrain_borders.clone();
+        terrain_rescaled.terrain.height_map_scale = 0.5;
+        assert!(terrain_borders.changes_maps(&terrain_rescaled));
+    }
 }
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{loading_screen::AppState::InGame, map_mode::ProvinceOwnerColors, planet};
+
+/// A player or AI power that can own provinces.
+#[derive(Component, Debug, Clone)]
+pub struct Faction {
+    pub name: String,
+    pub color: Color,
+    pub treasury: f64,
+}
+
+/// The faction entity that currently owns a province.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Owner(pub Entity);
+
+/// Request to hand a province to a new owner, or to release it when `new_owner` is `None`.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct TransferProvince {
+    pub province: Entity,
+    pub new_owner: Option<Entity>,
+}
+
+/// Sent once a province has changed hands to a faction.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ProvinceCaptured {
+    pub province: Entity,
+    pub previous_owner: Option<Entity>,
+    pub new_owner: Entity,
+}
+
+/// Sent once a faction has let go of a province, which is left without an owner.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ProvinceReleased {
+    pub province: Entity,
+    pub previous_owner: Entity,
+}
+
+pub struct FactionsPlugin;
+
+impl Plugin for FactionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TransferProvince>()
+            .add_event::<ProvinceCaptured>()
+            .add_event::<ProvinceReleased>()
+            .add_systems(
+                Update,
+                (
+                    transfer_province_ownership,
+                    log_captured_provinces,
+                    log_released_provinces,
+                    sync_owner_colors,
+                )
+                    .chain()
+                    .run_if(in_state(InGame)),
+            );
+    }
+}
+
+fn transfer_province_ownership(
+    mut commands: Commands,
+    mut transfer_evr: EventReader<TransferProvince>,
+    mut captured_evw: EventWriter<ProvinceCaptured>,
+    mut released_evw: EventWriter<ProvinceReleased>,
+    provinces_query: Query<Option<&Owner>, With<planet::Province>>,
+    factions_query: Query<(), With<Faction>>,
+) {
+    // Owners only change once the commands are applied, so a province transferred more than
+    // once this frame takes its previous owner from the earlier transfer
+    let mut changed_owners: HashMap<Entity, Option<Entity>> = HashMap::new();
+    for transfer in transfer_evr.read() {
+        let Ok(current_owner) = provinces_query.get(transfer.province) else {
+            warn!(
+                "Tried to transfer {:?} which is not a province",
+                transfer.province
+            );
+            continue;
+        };
+        let previous_owner = match changed_owners.get(&transfer.province) {
+            Some(changed_owner) => *changed_owner,
+            None => current_owner.map(|owner| owner.0),
+        };
+        if previous_owner == transfer.new_owner {
+            continue;
+        }
+
+        match transfer.new_owner {
+            Some(new_owner) => {
+                if factions_query.get(new_owner).is_err() {
+                    warn!(
+                        "Tried to transfer a province to {:?} which is not a faction",
+                        new_owner
+                    );
+                    continue;
+                }
+                commands.entity(transfer.province).insert(Owner(new_owner));
+                changed_owners.insert(transfer.province, Some(new_owner));
+                captured_evw.send(ProvinceCaptured {
+                    province: transfer.province,
+                    previous_owner,
+                    new_owner,
+                });
+            }
+            None => {
+                commands.entity(transfer.province).remove::<Owner>();
+                changed_owners.insert(transfer.province, None);
+                if let Some(previous_owner) = previous_owner {
+                    released_evw.send(ProvinceReleased {
+                        province: transfer.province,
+                        previous_owner,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn log_captured_provinces(
+    mut captured_evr: EventReader<ProvinceCaptured>,
+    provinces_query: Query<&planet::Province>,
+    factions_query: Query<&Faction>,
+) {
+    for captured in captured_evr.read() {
+        if let (Ok(province), Ok(faction)) = (
+            provinces_query.get(captured.province),
+            factions_query.get(captured.new_owner),
+        ) {
+            let previous_name = captured
+                .previous_owner
+                .and_then(|previous_owner| factions_query.get(previous_owner).ok())
+                .map_or("nobody", |previous_faction| previous_faction.name.as_str());
+            info!(
+                "{} captured province with ID: {} from {}",
+                faction.name, province.id, previous_name
+            );
+        }
+    }
+}
+
+fn log_released_provinces(
+    mut released_evr: EventReader<ProvinceReleased>,
+    provinces_query: Query<&planet::Province>,
+    factions_query: Query<&Faction>,
+) {
+    for released in released_evr.read() {
+        if let (Ok(province), Ok(faction)) = (
+            provinces_query.get(released.province),
+            factions_query.get(released.previous_owner),
+        ) {
+            info!(
+                "{} released province with ID: {}",
+                faction.name, province.id
+            );
+        }
+    }
+}
+
+/// Keeps the political map colors in step with province owners and faction colors.
+fn sync_owner_colors(
+    provinces_query: Query<(&planet::Province, &Owner)>,
+    changed_owners: Query<(), Changed<Owner>>,
+    mut removed_owners: RemovedComponents<Owner>,
+    changed_factions: Query<(), Changed<Faction>>,
+    factions_query: Query<&Faction>,
+    mut owner_colors: ResMut<ProvinceOwnerColors>,
+) {
+    let owners_removed = removed_owners.read().count() > 0;
+    if changed_owners.is_empty() && changed_factions.is_empty() && !owners_removed {
+        return;
+    }
+
+    owner_colors.0.clear();
+    for (province, owner) in provinces_query.iter() {
+        if let Ok(faction) = factions_query.get(owner.0) {
+            owner_colors.0.insert(province.id, faction.color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfers_report_captures_and_releases() {
+        let mut app = App::new();
+        app.add_event::<TransferProvince>()
+            .add_event::<ProvinceCaptured>()
+            .add_event::<ProvinceReleased>()
+            .add_systems(Update, transfer_province_ownership);
+        let faction = app
+            .world
+            .spawn(Faction {
+                name: "Tharsis League".to_owned(),
+                color: Color::RED,
+                treasury: 0.0,
+            })
+            .id();
+        let province = app
+            .world
+            .spawn(planet::Province {
+                id: 0,
+                color: [1, 2, 3],
+            })
+            .id();
+
+        app.world.send_event(TransferProvince {
+            province,
+            new_owner: Some(faction),
+        });
+        app.update();
+        assert_eq!(app.world.get::<Owner>(province), Some(&Owner(faction)));
+        let captured: Vec<ProvinceCaptured> = app
+            .world
+            .resource_mut::<Events<ProvinceCaptured>>()
+            .drain()
+            .collect();
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].new_owner, faction);
+        assert_eq!(captured[0].previous_owner, None);
+
+        app.world.send_event(TransferProvince {
+            province,
+            new_owner: None,
+        });
+        app.update();
+        assert_eq!(app.world.get::<Owner>(province), None);
+        let released: Vec<ProvinceReleased> = app
+            .world
+            .resource_mut::<Events<ProvinceReleased>>()
+            .drain()
+            .collect();
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].province, province);
+        assert_eq!(released[0].previous_owner, faction);
+        assert!(app.world.resource::<Events<ProvinceCaptured>>().is_empty());
+
+        // Releasing a province nobody owns changes nothing
+        app.world.send_event(TransferProvince {
+            province,
+            new_owner: None,
+        });
+        app.update();
+        assert!(app
+            .world
+            .resource_mut::<Events<ProvinceReleased>>()
+            .drain()
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn transfers_in_one_frame_see_each_other() {
+        let mut app = App::new();
+        app.add_event::<TransferProvince>()
+            .add_event::<ProvinceCaptured>()
+            .add_event::<ProvinceReleased>()
+            .add_systems(Update, transfer_province_ownership);
+        let [first, second] = ["Tharsis League", "Hellas Accord"].map(|name| {
+            app.world
+                .spawn(Faction {
+                    name: name.to_owned(),
+                    color: Color::RED,
+                    treasury: 0.0,
+                })
+                .id()
+        });
+        let province = app
+            .world
+            .spawn(planet::Province {
+                id: 0,
+                color: [1, 2, 3],
+            })
+            .id();
+
+        for new_owner in [Some(first), Some(second), Some(second), None, None] {
+            app.world.send_event(TransferProvince {
+                province,
+                new_owner,
+            });
+        }
+        app.update();
+        assert_eq!(app.world.get::<Owner>(province), None);
+        let captured: Vec<(Option<Entity>, Entity)> = app
+            .world
+            .resource_mut::<Events<ProvinceCaptured>>()
+            .drain()
+            .map(|captured| (captured.previous_owner, captured.new_owner))
+            .collect();
+        assert_eq!(captured, vec![(None, first), (Some(first), second)]);
+        let released: Vec<Entity> = app
+            .world
+            .resource_mut::<Events<ProvinceReleased>>()
+            .drain()
+            .map(|released| released.previous_owner)
+            .collect();
+        assert_eq!(released, vec![second]);
+    }
+}
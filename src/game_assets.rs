@@ -23,22 +23,31 @@ pub struct ColorMapAssets {
     pub positive_z: Handle<Image>,
 }
 
-#[derive(AssetCollection, Resource)]
-pub struct NormalMapAssets {
-    #[asset(path = "textures/mars/normal/left.png")]
-    pub negative_x: Handle<Image>,
-    #[asset(path = "textures/mars/normal/bottom.png")]
-    pub negative_y: Handle<Image>,
-    #[asset(path = "textures/mars/normal/back.png")]
-    pub negative_z: Handle<Image>,
-    #[asset(path = "textures/mars/normal/right.png")]
-    pub positive_x: Handle<Image>,
-    #[asset(path = "textures/mars/normal/top.png")]
-    pub positive_y: Handle<Image>,
-    #[asset(path = "textures/mars/normal/front.png")]
-    pub positive_z: Handle<Image>,
+impl ColorMapAssets {
+    /// The color maps in the face order used by every per-face list on the planet.
+    pub fn faces(&self) -> [&Handle<Image>; 6] {
+        return [
+            &self.positive_x,
+            &self.negative_x,
+            &self.positive_y,
+            &self.negative_y,
+            &self.positive_z,
+            &self.negative_z,
+        ];
+    }
 }
 
+/// Hand-made normal maps, only loaded with `terrain.normal_maps: files`. In the face order of
+/// `HeightMapAssets::faces`.
+pub const NORMAL_MAP_PATHS: [&str; 6] = [
+    "textures/mars/normal/right.png",
+    "textures/mars/normal/left.png",
+    "textures/mars/normal/top.png",
+    "textures/mars/normal/bottom.png",
+    "textures/mars/normal/front.png",
+    "textures/mars/normal/back.png",
+];
+
 #[derive(AssetCollection, Resource)]
 pub struct HeightMapAssets {
     #[asset(path = "textures/mars/height/left.png")]
@@ -54,3 +63,17 @@ pub struct HeightMapAssets {
     #[asset(path = "textures/mars/height/front.png")]
     pub positive_z: Handle<Image>,
 }
+
+impl HeightMapAssets {
+    /// The height maps in the face order used by every per-face list on the planet.
+    pub fn faces(&self) -> [&Handle<Image>; 6] {
+        return [
+            &self.positive_x,
+            &self.negative_x,
+            &self.positive_y,
+            &self.negative_y,
+            &self.positive_z,
+            &self.negative_z,
+        ];
+    }
+}
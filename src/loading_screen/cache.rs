@@ -0,0 +1,499 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        render_resource::PrimitiveTopology,
+    },
+};
+use image::{Rgb, RgbaImage};
+
+use super::ComputedMaps;
+use crate::{config_parser, planet};
+
+const CACHE_DIRECTORY: &str = "cache";
+
+/// Bumped whenever the layout of a cache file, or what generation makes of the same config,
+/// changes so stale caches are skipped.
+const CACHE_VERSION: u32 = 4;
+const CACHE_MAGIC: &[u8; 4] = b"RSGC";
+
+/// Hashes everything that feeds planet generation, so a cache is only reused when the
+/// config, seed and height maps are exactly the ones it was built from.
+pub fn cache_key(
+    engine_config: &config_parser::EngineConfig,
+    height_maps: &[Image],
+) -> Option<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&CACHE_VERSION.to_le_bytes());
+    // Camera settings do not feed generation, so tuning them keeps the cache valid
+    let generation_config = config_parser::EngineConfig {
+        camera: config_parser::CameraConfig::default(),
+        ..engine_config.clone()
+    };
+    hasher.update(serde_yaml::to_string(&generation_config).ok()?.as_bytes());
+    // Procedural height maps follow from the config, only authored ones need hashing
+    if engine_config.terrain.source == config_parser::TerrainSource::Textures {
+        for height_map in height_maps {
+            hasher.update(&height_map.data);
+        }
+    }
+    return Some(hasher.finalize().to_hex().to_string());
+}
+
+fn cache_path(key: &str, name: &str) -> PathBuf {
+    return Path::new(CACHE_DIRECTORY)
+        .join(key)
+        .join(format!("{}.bin", name));
+}
+
+fn write_cache_file(path: &Path, writer: CacheWriter) {
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(path, writer.bytes));
+    if let Err(error) = result {
+        warn!("Could not write generation cache {:?}: {}", path, error);
+    }
+}
+
+fn read_cache_file(path: &Path) -> Option<Vec<u8>> {
+    let bytes = fs::read(path).ok()?;
+    let mut reader = CacheReader::new(&bytes);
+    if reader.bytes(4)? != CACHE_MAGIC || reader.u32()? != CACHE_VERSION {
+        warn!("Ignoring outdated generation cache {:?}", path);
+        return None;
+    }
+    return Some(bytes[reader.position..].to_vec());
+}
+
+pub fn write_maps(key: &str, maps: &ComputedMaps) {
+    let mut writer = CacheWriter::new();
+    writer.u32(maps.province_data.len() as u32);
+    for color in &maps.province_data {
+        writer.raw(&color.0);
+    }
+
+    writer.u32(maps.province_map.dimensions);
+    writer.u32(maps.province_map.faces.len() as u32);
+    for face in &maps.province_map.faces {
+        writer.u32s(face);
+    }
+
+    writer.u32(maps.border_data.len() as u32);
+    for border_image in &maps.border_data {
+        writer.u32(border_image.width());
+        writer.u32(border_image.height());
+        writer.raw(border_image.as_raw());
+    }
+
+    let mut borders: Vec<(u32, u32, u32)> = maps
+        .province_graph
+        .neighbors
+        .iter()
+        .flat_map(|(a, neighbors)| neighbors.iter().map(move |(b, len)| (*a, *b, *len)))
+        .filter(|(a, b, _)| a < b)
+        .collect();
+    borders.sort();
+    writer.u32(borders.len() as u32);
+    for (a, b, length) in borders {
+        writer.u32(a);
+        writer.u32(b);
+        writer.u32(length);
+    }
+
+    for geometry in &maps.province_geometry {
+        writer.vec3(geometry.centroid);
+        writer.u32(geometry.area_pixels);
+        writer.f32(geometry.area_km2);
+        writer.f32(geometry.min_latitude);
+        writer.f32(geometry.max_latitude);
+        writer.f32(geometry.min_longitude);
+        writer.f32(geometry.max_longitude);
+        writer.u32(geometry.faces.len() as u32);
+        for face in &geometry.faces {
+            writer.vec3(*face);
+        }
+    }
+    write_cache_file(&cache_path(key, "maps"), writer);
+}
+
+/// Reads the maps back, treating a file that is truncated or does not describe a complete
+/// planet as a miss. Counts come straight from the file, so nothing is allocated up front.
+pub fn read_maps(key: &str) -> Option<ComputedMaps> {
+    let path = cache_path(key, "maps");
+    let bytes = read_cache_file(&path)?;
+    let mut reader = CacheReader::new(&bytes);
+
+    let province_count = reader.u32()? as usize;
+    let mut province_data: Vec<Rgb<u8>> = Vec::new();
+    for _ in 0..province_count {
+        let color = reader.bytes(3)?;
+        province_data.push(Rgb([color[0], color[1], color[2]]));
+    }
+
+    let dimensions = reader.u32()?;
+    let face_count = reader.u32()? as usize;
+    let mut faces: Vec<Vec<planet::ProvinceId>> = Vec::new();
+    for _ in 0..face_count {
+        faces.push(reader.u32s()?);
+    }
+    let province_map = planet::ProvinceMap { dimensions, faces };
+    if let Err(problem) = province_map.validate(province_count) {
+        warn!("Ignoring damaged generation cache {:?}: {}", path, problem);
+        return None;
+    }
+
+    let border_count = reader.u32()? as usize;
+    let mut border_data: Vec<RgbaImage> = Vec::new();
+    for _ in 0..border_count {
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        // Sizes come straight from the file, so a damaged one must not overflow
+        let byte_count = width.checked_mul(height)?.checked_mul(4)?;
+        let pixels = reader.bytes(byte_count as usize)?.to_vec();
+        border_data.push(RgbaImage::from_raw(width, height, pixels)?);
+    }
+    // Every face is drawn with the border image of the same index
+    if border_data.len() != province_map.faces.len()
+        || border_data
+            .iter()
+            .any(|image| image.dimensions() != (dimensions, dimensions))
+    {
+        warn!(
+            "Ignoring damaged generation cache {:?}: border images do not match the province map",
+            path
+        );
+        return None;
+    }
+
+    let mut province_graph = planet::ProvinceGraph::default();
+    for _ in 0..reader.u32()? {
+        let (a, b, length) = (reader.u32()?, reader.u32()?, reader.u32()?);
+        province_graph
+            .neighbors
+            .entry(a)
+            .or_default()
+            .insert(b, length);
+        province_graph
+            .neighbors
+            .entry(b)
+            .or_default()
+            .insert(a, length);
+    }
+
+    let mut province_geometry: Vec<planet::ProvinceGeometry> = Vec::new();
+    for _ in 0..province_count {
+        let centroid = reader.vec3()?;
+        let area_pixels = reader.u32()?;
+        let area_km2 = reader.f32()?;
+        let min_latitude = reader.f32()?;
+        let max_latitude = reader.f32()?;
+        let min_longitude = reader.f32()?;
+        let max_longitude = reader.f32()?;
+        let mut faces: Vec<Vec3> = Vec::new();
+        for _ in 0..reader.u32()? {
+            faces.push(reader.vec3()?);
+        }
+        province_geometry.push(planet::ProvinceGeometry {
+            centroid,
+            area_pixels,
+            area_km2,
+            min_latitude,
+            max_latitude,
+            min_longitude,
+            max_longitude,
+            faces,
+        });
+    }
+
+    return Some(ComputedMaps {
+        province_map,
+        province_data,
+        border_data,
+        province_graph,
+        province_geometry,
+    });
+}
+
+pub fn write_face_meshes(key: &str, suffix: &str, meshes: &[Mesh]) {
+    let mut writer = CacheWriter::new();
+    writer.u32(meshes.len() as u32);
+    for mesh in meshes {
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+            Some(VertexAttributeValues::Float32x2(uvs)),
+            Some(VertexAttributeValues::Float32x4(tangents)),
+            Some(Indices::U32(indices)),
+        ) = (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+            mesh.attribute(Mesh::ATTRIBUTE_UV_0),
+            mesh.attribute(Mesh::ATTRIBUTE_TANGENT),
+            mesh.indices(),
+        )
+        else {
+            warn!("Not caching {} meshes with unexpected attributes", suffix);
+            return;
+        };
+        writer.f32s(positions.as_flattened());
+        writer.f32s(normals.as_flattened());
+        writer.f32s(uvs.as_flattened());
+        writer.f32s(tangents.as_flattened());
+        writer.u32s(indices);
+    }
+    write_cache_file(&cache_path(key, suffix), writer);
+}
+
+pub fn read_face_meshes(key: &str, suffix: &str) -> Option<Vec<Mesh>> {
+    let bytes = read_cache_file(&cache_path(key, suffix))?;
+    let mut reader = CacheReader::new(&bytes);
+    let mesh_count = reader.u32()? as usize;
+    let mut meshes: Vec<Mesh> = Vec::new();
+    for _ in 0..mesh_count {
+        let positions = reader.f32s()?;
+        let normals = reader.f32s()?;
+        let uvs = reader.f32s()?;
+        let tangents = reader.f32s()?;
+        let indices = reader.u32s()?;
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, chunked::<3>(&positions));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, chunked::<3>(&normals));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, chunked::<2>(&uvs));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, chunked::<4>(&tangents));
+        meshes.push(mesh);
+    }
+    return Some(meshes);
+}
+
+fn chunked<const N: usize>(values: &[f32]) -> Vec<[f32; N]> {
+    return values
+        .chunks_exact(N)
+        .map(|chunk| {
+            let mut array = [0.0; N];
+            array.copy_from_slice(chunk);
+            array
+        })
+        .collect();
+}
+
+/// Little endian writer for the cache files.
+struct CacheWriter {
+    bytes: Vec<u8>,
+}
+
+impl CacheWriter {
+    fn new() -> Self {
+        let mut writer = CacheWriter { bytes: Vec::new() };
+        writer.raw(CACHE_MAGIC);
+        writer.u32(CACHE_VERSION);
+        return writer;
+    }
+
+    fn raw(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.raw(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.raw(&value.to_le_bytes());
+    }
+
+    fn vec3(&mut self, value: Vec3) {
+        self.f32(value.x);
+        self.f32(value.y);
+        self.f32(value.z);
+    }
+
+    fn u32s(&mut self, values: &[u32]) {
+        self.u32(values.len() as u32);
+        for value in values {
+            self.u32(*value);
+        }
+    }
+
+    fn f32s(&mut self, values: &[f32]) {
+        self.u32(values.len() as u32);
+        for value in values {
+            self.f32(*value);
+        }
+    }
+}
+
+/// Reads back what `CacheWriter` wrote, returning `None` on truncated files.
+struct CacheReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> CacheReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        return CacheReader { bytes, position: 0 };
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        return Some(bytes);
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        return Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?));
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        return Some(f32::from_le_bytes(self.bytes(4)?.try_into().ok()?));
+    }
+
+    fn vec3(&mut self) -> Option<Vec3> {
+        return Some(Vec3::new(self.f32()?, self.f32()?, self.f32()?));
+    }
+
+    fn u32s(&mut self) -> Option<Vec<u32>> {
+        let len = self.u32()? as usize;
+        return (0..len).map(|_| self.u32()).collect();
+    }
+
+    fn f32s(&mut self) -> Option<Vec<f32>> {
+        let len = self.u32()? as usize;
+        return (0..len).map(|_| self.f32()).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cache key of its own per test, removed again once the test is done with it.
+    struct ScratchKey(String);
+
+    impl ScratchKey {
+        fn new(name: &str) -> Self {
+            return ScratchKey(format!("test_{}_{}", name, std::process::id()));
+        }
+    }
+
+    impl Drop for ScratchKey {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(Path::new(CACHE_DIRECTORY).join(&self.0));
+        }
+    }
+
+    fn sample_maps() -> ComputedMaps {
+        let mut province_graph = planet::ProvinceGraph::default();
+        province_graph.add_border(0, 1);
+        province_graph.add_border(0, 1);
+        let geometry = |centroid: Vec3| planet::ProvinceGeometry {
+            centroid,
+            area_pixels: 12,
+            area_km2: 1.5e6,
+            min_latitude: -10.0,
+            max_latitude: 20.0,
+            min_longitude: 170.0,
+            max_longitude: -170.0,
+            faces: vec![Vec3::X, Vec3::Z],
+        };
+        return ComputedMaps {
+            province_map: planet::ProvinceMap {
+                dimensions: 2,
+                faces: vec![vec![0, 0, 1, 1]; 6],
+            },
+            province_data: vec![Rgb([10, 20, 30]), Rgb([40, 50, 60])],
+            border_data: vec![RgbaImage::from_pixel(2, 2, image::Rgba([0, 0, 0, 255])); 6],
+            province_graph,
+            province_geometry: vec![geometry(Vec3::X), geometry(Vec3::Z)],
+        };
+    }
+
+    #[test]
+    fn maps_round_trip() {
+        let key = ScratchKey::new("maps_round_trip");
+        let maps = sample_maps();
+        write_maps(&key.0, &maps);
+        let read = read_maps(&key.0).unwrap();
+
+        assert_eq!(read.province_map.dimensions, maps.province_map.dimensions);
+        assert_eq!(read.province_map.faces, maps.province_map.faces);
+        assert_eq!(read.province_data, maps.province_data);
+        assert_eq!(read.border_data, maps.border_data);
+        assert_eq!(read.province_graph.neighbors, maps.province_graph.neighbors);
+        assert_eq!(
+            format!("{:?}", read.province_geometry),
+            format!("{:?}", maps.province_geometry)
+        );
+    }
+
+    #[test]
+    fn damaged_maps_are_a_miss() {
+        let key = ScratchKey::new("damaged_maps");
+        write_maps(&key.0, &sample_maps());
+        let path = cache_path(&key.0, "maps");
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 5]).unwrap();
+        assert!(read_maps(&key.0).is_none());
+
+        // Border image sizes whose byte count overflows
+        let mut writer = CacheWriter::new();
+        writer.u32(0);
+        writer.u32(0);
+        writer.u32(0);
+        writer.u32(1);
+        writer.u32(u32::MAX);
+        writer.u32(u32::MAX);
+        write_cache_file(&path, writer);
+        assert!(read_maps(&key.0).is_none());
+
+        // Counts far beyond the file size run out of bytes instead of allocating up front
+        let mut writer = CacheWriter::new();
+        writer.u32(u32::MAX);
+        write_cache_file(&path, writer);
+        assert!(read_maps(&key.0).is_none());
+        let mut writer = CacheWriter::new();
+        writer.u32(u32::MAX);
+        write_cache_file(&cache_path(&key.0, "positive_x"), writer);
+        assert!(read_face_meshes(&key.0, "positive_x").is_none());
+    }
+
+    #[test]
+    fn incomplete_maps_are_a_miss() {
+        let key = ScratchKey::new("incomplete_maps");
+        let read_back = |maps: ComputedMaps| {
+            write_maps(&key.0, &maps);
+            return read_maps(&key.0);
+        };
+
+        let mut unknown_province = sample_maps();
+        unknown_province.province_map.faces[3][1] = 2;
+        assert!(read_back(unknown_province).is_none());
+
+        let mut missing_face = sample_maps();
+        missing_face.province_map.faces.pop();
+        assert!(read_back(missing_face).is_none());
+
+        let mut short_face = sample_maps();
+        short_face.province_map.faces[0].pop();
+        assert!(read_back(short_face).is_none());
+
+        let mut missing_border = sample_maps();
+        missing_border.border_data.pop();
+        assert!(read_back(missing_border).is_none());
+
+        let mut wrong_border_size = sample_maps();
+        wrong_border_size.border_data[5] = RgbaImage::new(3, 3);
+        assert!(read_back(wrong_border_size).is_none());
+
+        assert!(read_back(sample_maps()).is_some());
+    }
+}
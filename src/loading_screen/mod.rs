@@ -3,43 +3,59 @@ use bevy::{
     tasks::{AsyncComputeTaskPool, Task},
 };
 
-use image::{Rgb, RgbImage, RgbaImage};
+use image::{Rgb, RgbaImage};
 
 use bevy_asset_loader::prelude::*;
 use futures_lite::future;
 
+mod cache;
+
 use crate::camera_system;
 use crate::config_parser;
 use crate::game_assets;
 use crate::planet;
+use crate::province_picking;
+use crate::save_game;
 use crate::setup;
 use crate::skybox;
 
+struct ComputedMaps {
+    province_map: planet::ProvinceMap,
+    province_data: Vec<Rgb<u8>>,
+    border_data: Vec<RgbaImage>,
+    province_graph: planet::ProvinceGraph,
+    province_geometry: Vec<planet::ProvinceGeometry>,
+}
+
 #[derive(Component)]
-struct ComputeMapsComponent(Task<(Vec<RgbImage>, Vec<Rgb<u8>>, Vec<RgbaImage>)>);
+struct ComputeMapsComponent(Task<(planet::BuiltTerrain, ComputedMaps)>);
 
 #[derive(Component)]
-struct ComputeMeshesComponent(Task<(Vec3, String, Vec<Mesh>)>);
+struct ComputeMeshesComponent(Task<(planet::ChunkId, Mesh)>);
+
+#[derive(Component)]
+struct ComputeNormalMapsComponent(Task<Vec<Image>>);
 
 #[derive(Component)]
 struct LoadingScreenComponent;
 
+#[derive(Component)]
+struct ConfigErrorText;
+
 pub struct LoadingScreenPlugin;
 
 impl Plugin for LoadingScreenPlugin {
     fn build(&self, app: &mut App) {
         app.add_state::<AppState>()
-            .add_loading_state(
-                LoadingState::new(AppState::LoadingConfigs)
-                    .continue_to_state(AppState::LoadingImageAssets),
-            )
+            .add_systems(OnEnter(AppState::InvalidConfig), config_error_screen)
             .add_systems(
-                OnEnter(AppState::LoadingConfigs),
-                config_parser::read_configs,
+                Update,
+                update_config_error_screen.run_if(in_state(AppState::InvalidConfig)),
             )
+            .add_systems(OnExit(AppState::InvalidConfig), despawn_loading_screen)
             .add_loading_state(
                 LoadingState::new(AppState::LoadingImageAssets)
-                    .continue_to_state(AppState::GeneratingMaps),
+                    .continue_to_state(AppState::LoadingSave),
             )
             .add_collection_to_loading_state::<_, game_assets::ImageAssets>(
                 AppState::LoadingImageAssets,
@@ -47,14 +63,14 @@ impl Plugin for LoadingScreenPlugin {
             .add_collection_to_loading_state::<_, game_assets::ColorMapAssets>(
                 AppState::LoadingImageAssets,
             )
-            .add_collection_to_loading_state::<_, game_assets::NormalMapAssets>(
-                AppState::LoadingImageAssets,
-            )
             .add_collection_to_loading_state::<_, game_assets::HeightMapAssets>(
                 AppState::LoadingImageAssets,
             )
             .add_systems(OnEnter(AppState::LoadingImageAssets), loading_screen)
-            .add_systems(OnEnter(AppState::GeneratingMaps), setup_maps)
+            .add_systems(
+                OnEnter(AppState::GeneratingMaps),
+                (despawn_provinces, setup_maps).chain(),
+            )
             .add_systems(
                 Update,
                 handle_map_generation_tasks.run_if(in_state(AppState::GeneratingMaps)),
@@ -67,9 +83,12 @@ impl Plugin for LoadingScreenPlugin {
             .add_systems(
                 OnEnter(AppState::SpawningGameEntities),
                 (
+                    despawn_planet_faces,
                     planet::setup,
-                    skybox::build_skybox,
-                    setup::setup,
+                    // Lights and the skybox survive a config reload, only the planet is rebuilt
+                    (skybox::build_skybox, setup::setup)
+                        .chain()
+                        .run_if(not(resource_exists::<skybox::Cubemap>())),
                     finish_entity_spawning,
                 )
                     .chain(),
@@ -79,7 +98,9 @@ impl Plugin for LoadingScreenPlugin {
                 Update,
                 (
                     skybox::asset_loaded.run_if(in_state(AppState::InGame)),
-                    close_on_esc.run_if(in_state(AppState::InGame)),
+                    close_on_esc.run_if(
+                        in_state(AppState::InGame).or_else(in_state(AppState::InvalidConfig)),
+                    ),
                 ),
             );
     }
@@ -87,14 +108,14 @@ impl Plugin for LoadingScreenPlugin {
 
 fn setup_meshes(
     mut commands: Commands,
-    height_assets: Res<game_assets::HeightMapAssets>,
-    loaded_images: Res<Assets<Image>>,
+    terrain_maps: Res<planet::TerrainMaps>,
+    asset_server: Res<AssetServer>,
     engine_config: Res<config_parser::EngineConfig>,
 ) {
     let thread_pool = AsyncComputeTaskPool::get();
-    commands.insert_resource(planet::PlanetLODs {
-        level_of_detail_meshes: Vec::new(),
-    });
+    // Every face samples its neighbours along the seams, so all tasks share all height maps
+    let height_maps = terrain_maps.heights.clone();
+    let cache_key = cache::cache_key(&engine_config, &height_maps);
     let directions = [
         (Vec3::Y, "positive_y"),
         (Vec3::NEG_Y, "negative_y"),
@@ -103,28 +124,60 @@ fn setup_meshes(
         (Vec3::Z, "positive_z"),
         (Vec3::NEG_Z, "negative_z"),
     ];
+    commands.insert_resource(planet::PlanetQuadtree::new(
+        height_maps.clone(),
+        engine_config.lod.clone(),
+        engine_config.terrain.clone(),
+    ));
 
+    // The authored normal maps only match the authored height maps
+    let normal_maps = match engine_config.terrain.source {
+        config_parser::TerrainSource::Textures => engine_config.terrain.normal_maps,
+        config_parser::TerrainSource::Procedural => config_parser::NormalMapSource::Generated,
+    };
+    match normal_maps {
+        config_parser::NormalMapSource::Generated => {
+            let height_maps = height_maps.clone();
+            let terrain_config = engine_config.terrain.clone();
+            let task = thread_pool.spawn(async move {
+                return planet::generate_normal_maps(&height_maps, &terrain_config);
+            });
+            commands.spawn(ComputeNormalMapsComponent(task));
+        }
+        config_parser::NormalMapSource::Files => {
+            commands.insert_resource(planet::NormalMaps {
+                faces: game_assets::NORMAL_MAP_PATHS
+                    .iter()
+                    .map(|path| asset_server.load(*path))
+                    .collect(),
+            });
+        }
+    }
+
+    // Only the root chunks are built up front, the quadtree refines them once in game
     for (direction, suffix) in directions {
-        let height_handle = match suffix {
-            "positive_y" => &height_assets.positive_y,
-            "negative_y" => &height_assets.negative_y,
-            "negative_x" => &height_assets.negative_x,
-            "positive_x" => &height_assets.positive_x,
-            "positive_z" => &height_assets.positive_z,
-            "negative_z" => &height_assets.negative_z,
-            _ => continue,
+        let Some(face) = planet::face_index(direction) else {
+            continue;
         };
-        let height_handle_clone = height_handle.clone();
-        let height_map_clone = loaded_images.get(height_handle_clone).unwrap().clone();
-        let planet_lods = engine_config.planet_lods.clone();
+        let chunk = planet::ChunkId::root(face);
+        let height_maps = height_maps.clone();
+        let lod_config = engine_config.lod.clone();
+        let terrain_config = engine_config.terrain.clone();
+        let key = cache_key.clone();
 
         let task = thread_pool.spawn(async move {
-            let mut faces: Vec<Mesh> = Vec::with_capacity(planet_lods.len() as usize);
-            for res in planet_lods {
-                let planet_face = planet::spawn_face(direction, &height_map_clone, res);
-                faces.push(planet_face);
+            if let Some(mesh) = key
+                .as_ref()
+                .and_then(|key| cache::read_face_meshes(key, suffix))
+                .and_then(|meshes| meshes.into_iter().next())
+            {
+                return (chunk, mesh);
             }
-            return (direction, suffix.to_owned(), faces);
+            let mesh = planet::spawn_chunk(chunk, &height_maps, &lod_config, &terrain_config);
+            if let Some(key) = key {
+                cache::write_face_meshes(&key, suffix, std::slice::from_ref(&mesh));
+            }
+            return (chunk, mesh);
         });
 
         commands.spawn(()).insert(ComputeMeshesComponent(task));
@@ -134,41 +187,100 @@ fn setup_meshes(
 fn handle_mesh_generation_tasks(
     mut commands: Commands,
     mut tasks: Query<(Entity, &mut ComputeMeshesComponent)>,
+    mut normal_tasks: Query<(Entity, &mut ComputeNormalMapsComponent)>,
     mut state: ResMut<NextState<AppState>>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut planet_lods: ResMut<planet::PlanetLODs>,
+    mut images: ResMut<Assets<Image>>,
+    mut quadtree: ResMut<planet::PlanetQuadtree>,
 ) {
     for (entity, mut task_component) in tasks.iter_mut() {
         let future = future::block_on(future::poll_once(&mut task_component.0));
-        if let Some((direction, suffix, planet_lods_computed)) = future {
-            let mut handle_faces: Vec<Handle<Mesh>> =
-                Vec::with_capacity(planet_lods_computed.len());
-            for face in planet_lods_computed {
-                handle_faces.push(meshes.add(face));
-            }
-            planet_lods
-                .level_of_detail_meshes
-                .push((direction, suffix, handle_faces));
+        if let Some((chunk, mesh)) = future {
+            quadtree.insert_mesh(chunk, mesh, &mut meshes);
+            commands.entity(entity).despawn();
+        }
+    }
+    for (entity, mut task_component) in normal_tasks.iter_mut() {
+        let future = future::block_on(future::poll_once(&mut task_component.0));
+        if let Some(normal_maps) = future {
+            commands.insert_resource(planet::NormalMaps {
+                faces: normal_maps
+                    .into_iter()
+                    .map(|normal_map| images.add(normal_map))
+                    .collect(),
+            });
             commands.entity(entity).despawn();
         }
     }
-    if tasks.iter().count() <= 0 {
+    if tasks.iter().count() == 0 && normal_tasks.iter().count() == 0 {
         info!(target: "red_sand::loading_state::systems", "Loading state 'red_sand::loading_screen::AppState::GeneratingMeshes' is done");
         state.set(AppState::SpawningGameEntities);
     }
 }
 
-fn setup_maps(mut commands: Commands, engine_config: Res<config_parser::EngineConfig>) {
+fn setup_maps(
+    mut commands: Commands,
+    engine_config: Res<config_parser::EngineConfig>,
+    height_assets: Res<game_assets::HeightMapAssets>,
+    color_assets: Res<game_assets::ColorMapAssets>,
+    images: Res<Assets<Image>>,
+) {
     let thread_pool = AsyncComputeTaskPool::get();
     let num_provinces: u32 = engine_config.num_provinces;
     let map_dimensions: u32 = engine_config.map_dimensions;
+    let seed: u64 = engine_config.seed;
+    let provinces_config = engine_config.provinces.clone();
+    let noise_config = engine_config.noise.clone();
+    let terrain_config = engine_config.terrain.clone();
+
+    let terrain_tasks = planet::spawn_terrain_tasks(
+        seed,
+        &terrain_config,
+        height_assets.faces(),
+        color_assets.faces(),
+        &images,
+    );
+    let engine_config = engine_config.clone();
     let task = thread_pool.spawn(async move {
-        let colors = planet::create_province_colors_async(num_provinces, map_dimensions).await;
-        let provinces_map = planet::create_province_images_async(colors, map_dimensions).await;
+        let terrain = terrain_tasks.join().await;
+        let height_maps = terrain.heights.clone();
+        let cache_key = cache::cache_key(&engine_config, &height_maps);
+        if let Some(computed_maps) = cache_key.as_ref().and_then(|key| cache::read_maps(key)) {
+            return (terrain, computed_maps);
+        }
+        let colors =
+            planet::create_province_colors_async(num_provinces, map_dimensions, seed).await;
+        let provinces_map = planet::create_province_images_async(
+            colors,
+            map_dimensions,
+            seed,
+            provinces_config,
+            noise_config,
+            height_maps,
+            terrain_config,
+        )
+        .await;
         let province_data = planet::create_province_data_async(provinces_map.clone()).await;
         let border_data =
             planet::create_border_images_async(provinces_map.clone(), map_dimensions).await;
-        return (provinces_map, province_data, border_data);
+        let province_graph =
+            planet::create_province_graph_async(provinces_map.clone(), province_data.clone()).await;
+        let province_geometry =
+            planet::create_province_geometry_async(provinces_map.clone(), province_data.clone())
+                .await;
+        let province_map =
+            planet::create_province_map_async(provinces_map.clone(), province_data.clone()).await;
+        let computed_maps = ComputedMaps {
+            province_map,
+            province_data,
+            border_data,
+            province_graph,
+            province_geometry,
+        };
+        if let Some(key) = cache_key {
+            cache::write_maps(&key, &computed_maps);
+        }
+        return (terrain, computed_maps);
     });
 
     commands.spawn(()).insert(ComputeMapsComponent(task));
@@ -178,24 +290,31 @@ fn handle_map_generation_tasks(
     mut commands: Commands,
     mut tasks: Query<(Entity, &mut ComputeMapsComponent)>,
     mut state: ResMut<NextState<AppState>>,
+    mut images: ResMut<Assets<Image>>,
 ) {
     for (entity, mut task_component) in tasks.iter_mut() {
         let future = future::block_on(future::poll_once(&mut task_component.0));
-        if let Some((provinces_map, province_data, border_data)) = future {
-            for (province_id, color) in province_data.iter().enumerate() {
-                commands.spawn(planet::Province {
-                    id: province_id as i32,
-                    color: color.0,
-                });
-            }
-            for province_map in &provinces_map {
-                commands.insert_resource(planet::MapImage {
-                    image: province_map.clone(),
-                })
+        if let Some((terrain, computed_maps)) = future {
+            commands.insert_resource(terrain.into_maps(&mut images));
+            for ((province_id, color), geometry) in computed_maps
+                .province_data
+                .iter()
+                .enumerate()
+                .zip(computed_maps.province_geometry)
+            {
+                commands.spawn((
+                    planet::Province {
+                        id: province_id as planet::ProvinceId,
+                        color: color.0,
+                    },
+                    geometry,
+                ));
             }
+            commands.insert_resource(computed_maps.province_map);
             commands.insert_resource(planet::BorderImages {
-                border_images: border_data,
+                border_images: computed_maps.border_data,
             });
+            commands.insert_resource(computed_maps.province_graph);
             commands.entity(entity).remove::<ComputeMapsComponent>();
             info!(target: "red_sand::loading_state::systems", "Loading state 'red_sand::loading_screen::AppState::GeneratingMaps' is done");
             state.set(AppState::GeneratingMeshes);
@@ -203,6 +322,30 @@ fn handle_map_generation_tasks(
     }
 }
 
+/// Clears the provinces of a previous planet before its maps are regenerated.
+fn despawn_provinces(
+    mut commands: Commands,
+    provinces_query: Query<Entity, With<planet::Province>>,
+    mut hovered: ResMut<province_picking::HoveredProvince>,
+    mut selected: ResMut<province_picking::SelectedProvince>,
+) {
+    for entity in provinces_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    hovered.0 = None;
+    selected.0 = None;
+}
+
+/// The old planet stays visible while a reload regenerates it and is swapped out here.
+fn despawn_planet_faces(
+    mut commands: Commands,
+    planet_query: Query<Entity, With<planet::PlanetEntity>>,
+) {
+    for entity in planet_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
 fn finish_entity_spawning(mut state: ResMut<NextState<AppState>>) {
     state.set(AppState::InGame);
 }
@@ -211,16 +354,31 @@ fn enter_game(
     mut commands: Commands,
     loading_query: Query<Entity, With<LoadingScreenComponent>>,
     skybox_cubemap: Res<game_assets::ImageAssets>,
+    saved_camera: Option<Res<save_game::SavedCamera>>,
+    camera_query: Query<(), With<camera_system::ThirdPersonCamera>>,
+    engine_config: Res<config_parser::EngineConfig>,
 ) {
     for loading_component in loading_query.iter() {
         commands.entity(loading_component).despawn();
     }
+    if !camera_query.is_empty() {
+        return;
+    }
+    let mut transform = Transform::from_xyz(-2.0, 2.5, 5.0).looking_at(Vec3::ZERO, Vec3::Y);
+    let mut third_person_camera = camera_system::ThirdPersonCamera::new(&engine_config.camera);
+    if let Some(saved_camera) = saved_camera {
+        transform.rotation = saved_camera.rotation;
+        third_person_camera
+            .zoom
+            .set_radius(saved_camera.zoom_radius);
+        commands.remove_resource::<save_game::SavedCamera>();
+    }
     let camera = (
         Camera3dBundle {
-            transform: Transform::from_xyz(-2.0, 2.5, 5.0).looking_at(Vec3::ZERO, Vec3::Y),
+            transform,
             ..default()
         },
-        camera_system::ThirdPersonCamera::default(),
+        third_person_camera,
         bevy::core_pipeline::Skybox(skybox_cubemap.skybox_texture.clone()),
     );
     commands.spawn(camera);
@@ -241,6 +399,50 @@ fn loading_screen(mut commands: Commands) {
     ));
 }
 
+fn config_error_screen(mut commands: Commands, config_errors: Res<config_parser::ConfigErrors>) {
+    commands.spawn((Camera2dBundle::default(), LoadingScreenComponent));
+    commands.spawn((
+        TextBundle::from_section(
+            config_error_message(&config_errors),
+            TextStyle {
+                color: Color::rgb(1.0, 0.4, 0.3),
+                ..default()
+            },
+        )
+        .with_text_alignment(TextAlignment::Left),
+        LoadingScreenComponent,
+        ConfigErrorText,
+    ));
+}
+
+fn config_error_message(config_errors: &config_parser::ConfigErrors) -> String {
+    return format!(
+        "{}\n\nFix the config to continue, or press Esc to quit.",
+        config_errors.0
+    );
+}
+
+fn update_config_error_screen(
+    config_errors: Res<config_parser::ConfigErrors>,
+    mut text_query: Query<&mut Text, With<ConfigErrorText>>,
+) {
+    if !config_errors.is_changed() {
+        return;
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = config_error_message(&config_errors);
+    }
+}
+
+fn despawn_loading_screen(
+    mut commands: Commands,
+    loading_query: Query<Entity, With<LoadingScreenComponent>>,
+) {
+    for loading_component in loading_query.iter() {
+        commands.entity(loading_component).despawn();
+    }
+}
+
 fn close_on_esc(
     mut commands: Commands,
     focused_windows: Query<(Entity, &Window)>,
@@ -261,7 +463,9 @@ fn close_on_esc(
 pub enum AppState {
     #[default]
     LoadingConfigs,
+    InvalidConfig,
     LoadingImageAssets,
+    LoadingSave,
     GeneratingMaps,
     GeneratingMeshes,
     SpawningGameEntities,
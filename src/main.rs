@@ -1,8 +1,12 @@
 mod camera_system;
 mod config_parser;
+mod factions;
 mod game_assets;
 mod loading_screen;
+mod map_mode;
 mod planet;
+mod province_picking;
+mod save_game;
 mod setup;
 mod skybox;
 
@@ -12,12 +16,28 @@ use bevy::{
 };
 use bevy_mod_raycast::prelude::*;
 use camera_system::ThirdPersonCameraPlugin;
-use planet::PlanetMaterial;
+use factions::FactionsPlugin;
+use map_mode::MapModePlugin;
+use planet::{PlanetLodPlugin, PlanetMaterial};
+use province_picking::ProvincePickingPlugin;
+use save_game::SaveGamePlugin;
 
 fn main() {
     // TODO this is a debugging tool only and should be left out of prod
     // std::env::set_var("RUST_BACKTRACE", "1");
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some(planet::IMPORT_COMMAND) {
+        let result =
+            planet::ImportOptions::parse(args).and_then(|options| planet::import_map(&options));
+        if let Err(error) = result {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::new()
+        .add_plugins(config_parser::ConfigPlugin::from_env())
         .add_plugins((
             DefaultPlugins
                 .set(bevy_mod_raycast::low_latency_window_plugin())
@@ -31,6 +51,11 @@ fn main() {
                 }),
             loading_screen::LoadingScreenPlugin,
             ThirdPersonCameraPlugin,
+            PlanetLodPlugin,
+            ProvincePickingPlugin,
+            MapModePlugin,
+            FactionsPlugin,
+            SaveGamePlugin,
             DefaultRaycastingPlugin,
             WireframePlugin,
             MaterialPlugin::<ExtendedMaterial<StandardMaterial, PlanetMaterial>>::default(),
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use bevy::{pbr::ExtendedMaterial, prelude::*};
+
+use crate::{loading_screen::AppState::InGame, planet};
+
+/// What the planet surface shows, matching the map mode constants in `planet.wgsl`.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MapMode {
+    #[default]
+    Terrain,
+    Political,
+    ProvinceId,
+}
+
+impl MapMode {
+    pub fn shader_index(self) -> u32 {
+        return match self {
+            MapMode::Terrain => 0,
+            MapMode::Political => 1,
+            MapMode::ProvinceId => 2,
+        };
+    }
+}
+
+/// The color each owned province is painted with in the political map mode.
+/// Provinces missing from the table are drawn as unowned terrain.
+#[derive(Resource, Debug, Default)]
+pub struct ProvinceOwnerColors(pub HashMap<planet::ProvinceId, Color>);
+
+pub struct MapModePlugin;
+
+impl Plugin for MapModePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapMode>()
+            .init_resource::<ProvinceOwnerColors>()
+            .add_systems(OnEnter(InGame), refresh_map_mode)
+            .add_systems(
+                Update,
+                (switch_map_mode, update_planet_map_mode)
+                    .chain()
+                    .run_if(in_state(InGame)),
+            );
+    }
+}
+
+/// A rebuilt planet starts out with fresh materials, so push the current mode to them again.
+fn refresh_map_mode(mut map_mode: ResMut<MapMode>) {
+    map_mode.set_changed();
+}
+
+fn switch_map_mode(input: Res<Input<KeyCode>>, mut map_mode: ResMut<MapMode>) {
+    let new_mode = if input.just_pressed(KeyCode::F1) {
+        MapMode::Terrain
+    } else if input.just_pressed(KeyCode::F2) {
+        MapMode::Political
+    } else if input.just_pressed(KeyCode::F3) {
+        MapMode::ProvinceId
+    } else {
+        return;
+    };
+    if *map_mode != new_mode {
+        *map_mode = new_mode;
+    }
+}
+
+fn update_planet_map_mode(
+    map_mode: Res<MapMode>,
+    owner_colors: Res<ProvinceOwnerColors>,
+    provinces_query: Query<&planet::Province>,
+    planet_q: Query<
+        &Handle<ExtendedMaterial<StandardMaterial, planet::PlanetMaterial>>,
+        With<planet::PlanetEntity>,
+    >,
+    mut planet_mats: ResMut<Assets<ExtendedMaterial<StandardMaterial, planet::PlanetMaterial>>>,
+) {
+    if !map_mode.is_changed() && !owner_colors.is_changed() {
+        return;
+    }
+
+    // The lookup table is indexed by province id, so it has to cover the largest id
+    let table_len = provinces_query
+        .iter()
+        .map(|province| province.id as usize + 1)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let mut color_table: Vec<Vec4> = vec![Vec4::ZERO; table_len];
+    for (province_id, color) in owner_colors.0.iter() {
+        if let Some(entry) = color_table.get_mut(*province_id as usize) {
+            *entry = Vec4::from_array(color.as_rgba_f32());
+        }
+    }
+
+    for material_handle in planet_q.iter() {
+        if let Some(material) = planet_mats.get_mut(material_handle) {
+            material.extension.map_mode = map_mode.shader_index();
+            material.extension.owner_colors = color_table.clone();
+        }
+    }
+}
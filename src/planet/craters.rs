@@ -0,0 +1,275 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{cube_map, planet_mesh};
+use crate::config_parser::CraterConfig;
+
+/// Side length in texels of the tiles craters are sorted into before stamping.
+const TILE_SIZE: u32 = 16;
+/// Fraction of the crater radius the central peak rises within.
+const PEAK_RADIUS: f32 = 0.2;
+/// Central peak height as a fraction of the bowl depth.
+const PEAK_HEIGHT: f32 = 0.5;
+/// Complex craters have flat floors, their bowls stop sinking at this fraction of the depth.
+const FLOOR_DEPTH: f32 = 0.8;
+
+/// A single impact on the unit sphere.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crater {
+    pub center: Vec3,
+    /// Angular radius of the rim in radians.
+    pub radius: f32,
+    /// Bowl depth below the surrounding terrain in height map units.
+    pub depth: f32,
+    pub central_peak: bool,
+}
+
+impl Crater {
+    /// Height added to the terrain at `distance` crater radii from the centre: a parabolic
+    /// bowl up to a raised rim, then an ejecta blanket thinning out until `ejecta_extent`.
+    fn profile(&self, distance: f32, config: &CraterConfig) -> f32 {
+        let rim = config.rim_height * self.depth;
+        if distance < 1.0 {
+            let mut bowl = distance * distance - 1.0;
+            if self.central_peak {
+                bowl = bowl.max(-FLOOR_DEPTH);
+                let peak = (1.0 - distance / PEAK_RADIUS).max(0.0);
+                bowl += PEAK_HEIGHT * peak * peak;
+            }
+            // The rim rises over the last stretch of the bowl wall
+            return self.depth * bowl + rim * distance.powi(4);
+        }
+        // Ejecta thickness falls off with the cube of the distance, shifted to end at zero
+        let tail = config.ejecta_extent.powi(-3);
+        return rim * ((distance.powi(-3) - tail) / (1.0 - tail)).max(0.0);
+    }
+}
+
+/// Scatters `config.count` craters uniformly over the sphere from the seed. Radii are drawn
+/// from a power law truncated to the configured range by inverting its cumulative
+/// distribution.
+pub fn scatter_craters(seed: u64, config: &CraterConfig) -> Vec<Crater> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let min_radius = config.min_radius.to_radians();
+    let max_radius = config.max_radius.to_radians();
+    let smallest = min_radius.powf(-config.size_exponent);
+    let largest = max_radius.powf(-config.size_exponent);
+
+    return (0..config.count)
+        .map(|_| {
+            let z: f32 = rng.gen_range(-1.0..=1.0);
+            let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+            let ring = (1.0 - z * z).max(0.0).sqrt();
+            let center = Vec3::new(ring * angle.cos(), ring * angle.sin(), z);
+
+            let share: f32 = rng.gen();
+            let radius = (smallest - share * (smallest - largest))
+                .powf(-1.0 / config.size_exponent)
+                .clamp(min_radius, max_radius);
+            Crater {
+                center,
+                radius,
+                depth: config.max_depth * radius / max_radius,
+                central_peak: radius > config.central_peak_radius.to_radians(),
+            }
+        })
+        .collect();
+}
+
+/// Angle in radians between two unit vectors, accurate for the small angles of tiny craters.
+fn angle_between(a: Vec3, b: Vec3) -> f32 {
+    return a.cross(b).length().atan2(a.dot(b));
+}
+
+/// Stamps the craters onto one height face, `face` indexing `FACE_DIRECTIONS`. Every texel
+/// is placed by its direction on the sphere, so stamping each face with the same craters
+/// carries craters on a seam over to all faces they touch. Returns a single channel float
+/// face at the input size, with heights kept in [0, 1] like every other height map.
+pub fn stamp_craters(
+    face: usize,
+    height_map: &Image,
+    craters: &[Crater],
+    config: &CraterConfig,
+) -> Image {
+    let size = height_map.texture_descriptor.size;
+    let texel_uv = |x: f32, y: f32| Vec2::new(x / size.width as f32, y / size.height as f32);
+    let mut data: Vec<u8> = Vec::with_capacity((size.width * size.height * 4) as usize);
+    let mut heights = vec![0.0; (size.width * size.height) as usize];
+
+    for tile_y in (0..size.height).step_by(TILE_SIZE as usize) {
+        for tile_x in (0..size.width).step_by(TILE_SIZE as usize) {
+            let end_x = (tile_x + TILE_SIZE).min(size.width);
+            let end_y = (tile_y + TILE_SIZE).min(size.height);
+            let tile_center = cube_map::face_point(
+                face,
+                texel_uv((tile_x + end_x) as f32 / 2.0, (tile_y + end_y) as f32 / 2.0),
+            )
+            .normalize();
+            let tile_reach = [
+                (tile_x, tile_y),
+                (end_x, tile_y),
+                (tile_x, end_y),
+                (end_x, end_y),
+            ]
+            .iter()
+            .map(|&(x, y)| {
+                let corner = cube_map::face_point(face, texel_uv(x as f32, y as f32));
+                angle_between(tile_center, corner.normalize())
+            })
+            .fold(0.0, f32::max);
+            let nearby: Vec<&Crater> = craters
+                .iter()
+                .filter(|crater| {
+                    angle_between(tile_center, crater.center)
+                        <= crater.radius * config.ejecta_extent + tile_reach
+                })
+                .collect();
+
+            for y in tile_y..end_y {
+                for x in tile_x..end_x {
+                    let direction =
+                        cube_map::face_point(face, texel_uv(x as f32 + 0.5, y as f32 + 0.5))
+                            .normalize();
+                    let mut height = planet_mesh::texel_height(height_map, x as i64, y as i64);
+                    for crater in &nearby {
+                        let distance = angle_between(direction, crater.center) / crater.radius;
+                        if distance < config.ejecta_extent {
+                            height += crater.profile(distance, config);
+                        }
+                    }
+                    heights[(y * size.width + x) as usize] = height.clamp(0.0, 1.0);
+                }
+            }
+        }
+    }
+    for height in heights {
+        data.extend_from_slice(&height.to_ne_bytes());
+    }
+    return Image::new(
+        Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::R32Float,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_parser::TerrainConfig;
+    use crate::planet::planet_mesh::HeightField;
+    use crate::planet::terrain::height_faces;
+
+    fn stamp_faces(height_maps: &[Image], craters: &[Crater], config: &CraterConfig) -> Vec<Image> {
+        return height_maps
+            .iter()
+            .enumerate()
+            .map(|(face, height_map)| stamp_craters(face, height_map, craters, config))
+            .collect();
+    }
+
+    #[test]
+    fn scattering_is_seeded_and_favours_small_craters() {
+        let config = CraterConfig {
+            count: 500,
+            ..default()
+        };
+        let craters = scatter_craters(3, &config);
+        assert_eq!(craters, scatter_craters(3, &config));
+        assert_ne!(craters, scatter_craters(4, &config));
+
+        let min_radius = config.min_radius.to_radians();
+        let max_radius = config.max_radius.to_radians();
+        assert!(craters
+            .iter()
+            .all(|crater| (min_radius..=max_radius).contains(&crater.radius)));
+        let small = craters
+            .iter()
+            .filter(|crater| crater.radius < 2.0 * min_radius)
+            .count();
+        assert!(small > craters.len() / 2, "{} small craters", small);
+        assert!(craters
+            .iter()
+            .all(|crater| (crater.center.length() - 1.0).abs() < 1e-4));
+    }
+
+    #[test]
+    fn craters_dig_a_bowl_inside_a_raised_rim() {
+        let config = CraterConfig::default();
+        let crater = Crater {
+            center: Vec3::X,
+            radius: 10f32.to_radians(),
+            depth: 0.1,
+            central_peak: false,
+        };
+        let stamped = stamp_faces(
+            &height_faces(64, |_| 0.5),
+            std::slice::from_ref(&crater),
+            &config,
+        );
+        let height_field = HeightField::new(&stamped, &TerrainConfig::default());
+        let at_distance = |distance: f32| {
+            let angle = crater.radius * distance;
+            height_field.height(Vec3::new(angle.cos(), angle.sin(), 0.0))
+        };
+
+        assert!(at_distance(0.0) < 0.5 - 0.08);
+        assert!(at_distance(1.0) > 0.5 + 0.01);
+        assert!(at_distance(1.5) > 0.5);
+        assert!(at_distance(1.5) < at_distance(1.0));
+        assert_eq!(at_distance(config.ejecta_extent + 0.5), 0.5);
+    }
+
+    #[test]
+    fn stamped_heights_stay_in_range() {
+        let config = CraterConfig::default();
+        let crater = Crater {
+            center: Vec3::Y,
+            radius: 10f32.to_radians(),
+            depth: 0.5,
+            central_peak: false,
+        };
+        for base in [0.05, 0.95] {
+            let stamped = stamp_faces(
+                &height_faces(16, |_| base),
+                std::slice::from_ref(&crater),
+                &config,
+            );
+            for face in &stamped {
+                for bytes in face.data.chunks_exact(4) {
+                    let height = f32::from_ne_bytes(bytes.try_into().unwrap());
+                    assert!((0.0..=1.0).contains(&height), "{}", height);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn craters_on_a_seam_mark_both_faces() {
+        let config = CraterConfig::default();
+        // Straddles the edge between the +X and +Z faces
+        let crater = Crater {
+            center: Vec3::new(1.0, 0.0, 1.0).normalize(),
+            radius: 10f32.to_radians(),
+            depth: 0.1,
+            central_peak: true,
+        };
+        let stamped = stamp_faces(&height_faces(64, |_| 0.5), &[crater], &config);
+        let height_field = HeightField::new(&stamped, &TerrainConfig::default());
+
+        let on_x = height_field.height(Vec3::new(1.0, 0.0, 0.9).normalize());
+        let on_z = height_field.height(Vec3::new(0.9, 0.0, 1.0).normalize());
+        assert!(on_x < 0.5 - 0.05);
+        assert!((on_x - on_z).abs() < 0.01, "{} against {}", on_x, on_z);
+        // The central peak rises out of the flat floor
+        let peak = height_field.height(Vec3::new(1.0, 0.0, 1.0).normalize());
+        assert!(peak > on_x);
+    }
+}
@@ -0,0 +1,108 @@
+use bevy::{math::Vec3Swizzles, prelude::*};
+
+/// The six cube faces in the order every per-face image list is stored in.
+pub const FACE_DIRECTIONS: [Vec3; 6] = [
+    Vec3::X,
+    Vec3::NEG_X,
+    Vec3::Y,
+    Vec3::NEG_Y,
+    Vec3::Z,
+    Vec3::NEG_Z,
+];
+
+/// The two axes spanning a face, matching the layout used by `planet_mesh::face`.
+pub fn face_axes(local_up: Vec3) -> (Vec3, Vec3) {
+    let axis_a = local_up.yzx();
+    let axis_b = local_up.cross(axis_a);
+    return (axis_a, axis_b);
+}
+
+pub fn face_index(direction: Vec3) -> Option<usize> {
+    return FACE_DIRECTIONS.iter().position(|face| *face == direction);
+}
+
+/// Maps a face uv in `[0, 1]` to a point on the surface of the unit cube.
+pub fn face_point(face: usize, uv: Vec2) -> Vec3 {
+    let local_up = FACE_DIRECTIONS[face];
+    let (axis_a, axis_b) = face_axes(local_up);
+    return local_up + (uv.x - 0.5) * 2.0 * axis_a + (uv.y - 0.5) * 2.0 * axis_b;
+}
+
+/// Center of a pixel on a face image of the given dimensions, as a point on the unit cube.
+pub fn pixel_point(face: usize, x: u32, y: u32, dimensions: u32) -> Vec3 {
+    let uv = Vec2::new(
+        (x as f32 + 0.5) / dimensions as f32,
+        (y as f32 + 0.5) / dimensions as f32,
+    );
+    return face_point(face, uv);
+}
+
+/// Finds the face a direction points through and the uv it hits on that face.
+pub fn direction_to_face_uv(direction: Vec3) -> (usize, Vec2) {
+    let abs = direction.abs();
+    let face = if abs.x >= abs.y && abs.x >= abs.z {
+        if direction.x >= 0.0 {
+            0
+        } else {
+            1
+        }
+    } else if abs.y >= abs.z {
+        if direction.y >= 0.0 {
+            2
+        } else {
+            3
+        }
+    } else if direction.z >= 0.0 {
+        4
+    } else {
+        5
+    };
+
+    let local_up = FACE_DIRECTIONS[face];
+    let (axis_a, axis_b) = face_axes(local_up);
+    let on_cube = direction / direction.dot(local_up);
+    let uv = Vec2::new(
+        on_cube.dot(axis_a) * 0.5 + 0.5,
+        on_cube.dot(axis_b) * 0.5 + 0.5,
+    );
+    return (face, uv.clamp(Vec2::ZERO, Vec2::ONE));
+}
+
+/// Latitude and longitude in degrees of a direction, with +Y as north.
+pub fn direction_to_lat_long(direction: Vec3) -> (f32, f32) {
+    let direction = direction.normalize();
+    let latitude = direction.y.clamp(-1.0, 1.0).asin().to_degrees();
+    let longitude = direction.x.atan2(-direction.z).to_degrees();
+    return (latitude, longitude);
+}
+
+/// Finds the face and pixel a direction points through on face images of the given dimensions.
+pub fn direction_to_pixel(direction: Vec3, dimensions: u32) -> (usize, u32, u32) {
+    let (face, uv) = direction_to_face_uv(direction);
+    let max = dimensions as f32 - 1.0;
+    let x = (uv.x * dimensions as f32).clamp(0.0, max) as u32;
+    let y = (uv.y * dimensions as f32).clamp(0.0, max) as u32;
+    return (face, x, y);
+}
+
+/// Steps one pixel from `(x, y)` on a face, wrapping over the seam onto the adjacent face
+/// when the step leaves the image.
+pub fn neighbor_pixel(
+    face: usize,
+    x: u32,
+    y: u32,
+    dx: i32,
+    dy: i32,
+    dimensions: u32,
+) -> (usize, u32, u32) {
+    let nx = x as i32 + dx;
+    let ny = y as i32 + dy;
+    if nx >= 0 && ny >= 0 && nx < dimensions as i32 && ny < dimensions as i32 {
+        return (face, nx as u32, ny as u32);
+    }
+    let uv = Vec2::new(
+        (nx as f32 + 0.5) / dimensions as f32,
+        (ny as f32 + 0.5) / dimensions as f32,
+    );
+    return direction_to_pixel(face_point(face, uv), dimensions);
+}
@@ -0,0 +1,129 @@
+/// A static 3D k-d tree answering nearest neighbour queries over a fixed set of points.
+/// The tree is stored implicitly: every slice is split around its median element.
+pub struct KdTree {
+    points: Vec<([f64; 3], usize)>,
+}
+
+impl KdTree {
+    /// Builds the tree from points paired with the index they should be reported as.
+    pub fn new(mut points: Vec<([f64; 3], usize)>) -> Self {
+        build(&mut points, 0);
+        return KdTree { points };
+    }
+
+    /// Index of the point closest to `target`, preferring the lowest index on exact ties.
+    pub fn nearest(&self, target: [f64; 3]) -> Option<usize> {
+        let mut best: Option<(f64, usize)> = None;
+        search(&self.points, 0, target, &mut best);
+        return best.map(|(_, index)| index);
+    }
+}
+
+fn build(points: &mut [([f64; 3], usize)], depth: usize) {
+    if points.len() <= 1 {
+        return;
+    }
+    let axis = depth % 3;
+    let mid = points.len() / 2;
+    points.select_nth_unstable_by(mid, |a, b| a.0[axis].total_cmp(&b.0[axis]));
+    let (left, right) = points.split_at_mut(mid);
+    build(left, depth + 1);
+    build(&mut right[1..], depth + 1);
+}
+
+fn search(
+    points: &[([f64; 3], usize)],
+    depth: usize,
+    target: [f64; 3],
+    best: &mut Option<(f64, usize)>,
+) {
+    if points.is_empty() {
+        return;
+    }
+    let axis = depth % 3;
+    let mid = points.len() / 2;
+    let (point, index) = points[mid];
+
+    let distance = (0..3).map(|i| (target[i] - point[i]).powi(2)).sum::<f64>();
+    let is_better = match *best {
+        Some((best_distance, best_index)) => {
+            distance < best_distance || (distance == best_distance && index < best_index)
+        }
+        None => true,
+    };
+    if is_better {
+        *best = Some((distance, index));
+    }
+
+    let offset = target[axis] - point[axis];
+    let (near, far) = if offset < 0.0 {
+        (&points[..mid], &points[mid + 1..])
+    } else {
+        (&points[mid + 1..], &points[..mid])
+    };
+    search(near, depth + 1, target, best);
+    if let Some((best_distance, _)) = *best {
+        if offset * offset <= best_distance {
+            search(far, depth + 1, target, best);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn brute_force_nearest(points: &[([f64; 3], usize)], target: [f64; 3]) -> Option<usize> {
+        return points
+            .iter()
+            .map(|(point, index)| {
+                let distance = (0..3).map(|i| (target[i] - point[i]).powi(2)).sum::<f64>();
+                (distance, *index)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)))
+            .map(|(_, index)| index);
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(5);
+        // Coarse integer coordinates give plenty of duplicates and exact ties
+        let mut points: Vec<([f64; 3], usize)> = (0..300)
+            .map(|index| {
+                let point = [
+                    rng.gen_range(0..12) as f64,
+                    rng.gen_range(0..12) as f64,
+                    rng.gen_range(0..12) as f64,
+                ];
+                (point, index)
+            })
+            .collect();
+        points.push((points[17].0, 400));
+        let tree = KdTree::new(points.clone());
+
+        for _ in 0..2000 {
+            let target = [
+                rng.gen_range(-2.0..14.0),
+                rng.gen_range(-2.0..14.0),
+                rng.gen_range(-2.0..14.0),
+            ];
+            assert_eq!(tree.nearest(target), brute_force_nearest(&points, target));
+        }
+        for (point, _) in &points {
+            let target = [point[0] + 0.5, point[1] + 0.5, point[2]];
+            assert_eq!(tree.nearest(target), brute_force_nearest(&points, target));
+        }
+        // Exactly on a duplicated point, where the lowest index has to win
+        assert_eq!(
+            tree.nearest(points[17].0),
+            brute_force_nearest(&points, points[17].0)
+        );
+        assert_ne!(tree.nearest(points[17].0), Some(400));
+    }
+
+    #[test]
+    fn empty_trees_find_nothing() {
+        assert_eq!(KdTree::new(Vec::new()).nearest([0.0; 3]), None);
+    }
+}
@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+
+use super::{PlanetEntity, PlanetQuadtree};
+use crate::{camera_system::ThirdPersonCamera, loading_screen::AppState::InGame};
+
+/// Refines and coarsens the face quadtrees every frame from wherever the camera is,
+/// independent of how it got there.
+pub struct PlanetLodPlugin;
+
+impl Plugin for PlanetLodPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (poll_chunk_meshes, update_planet_lod)
+                .chain()
+                .run_if(in_state(InGame).and_then(resource_exists::<PlanetQuadtree>())),
+        );
+    }
+}
+
+fn poll_chunk_meshes(mut quadtree: ResMut<PlanetQuadtree>, mut meshes: ResMut<Assets<Mesh>>) {
+    quadtree.poll_tasks(&mut meshes);
+}
+
+fn update_planet_lod(
+    mut commands: Commands,
+    mut quadtree: ResMut<PlanetQuadtree>,
+    camera_q: Query<&GlobalTransform, With<ThirdPersonCamera>>,
+    planet_q: Query<&GlobalTransform, With<PlanetEntity>>,
+) {
+    let (Ok(camera_transform), Some(planet_transform)) =
+        (camera_q.get_single(), planet_q.iter().next())
+    else {
+        return;
+    };
+    // Chunk bounds live in the faces' local space, which all faces share
+    let camera_position = planet_transform
+        .affine()
+        .inverse()
+        .transform_point3(camera_transform.translation());
+    quadtree.update(&mut commands, camera_position);
+}
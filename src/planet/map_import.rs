@@ -0,0 +1,360 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use image::{ImageBuffer, Luma, Rgba, RgbaImage};
+
+use super::{cube_map, normal_map};
+use crate::config_parser::TerrainConfig;
+
+/// First argument that runs the importer instead of the game.
+pub const IMPORT_COMMAND: &str = "import-map";
+
+pub const IMPORT_USAGE: &str = "\
+usage: red_sand import-map --height <path> [options]
+
+Reprojects an equirectangular elevation map, such as the MOLA DEM, into the six cube face
+height, color and normal textures the game loads.
+
+  --height <path>        Elevation map, any image format or raw 16 bit samples
+  --raw-size <W>x<H>     Read --height as raw signed 16 bit samples of this size
+  --little-endian        Raw samples are little endian, MOLA products are big endian
+  --color <path>         Equirectangular color map, heights are tinted when left out
+  --size <pixels>        Width and height of each face texture, 1024 by default
+  --height-scale <f32>   terrain.height_map_scale the normal maps are baked for
+  --out <dir>            Output directory, assets/textures/mars by default";
+
+/// File names of the faces in `FACE_DIRECTIONS` order, matching the paths in `game_assets`.
+const FACE_NAMES: [&str; 6] = ["right", "left", "top", "bottom", "front", "back"];
+
+const DEFAULT_FACE_SIZE: u32 = 1024;
+const DEFAULT_OUTPUT: &str = "assets/textures/mars";
+
+/// Colors the lowest and highest ground gets when no color map is given.
+const LOWLAND_COLOR: Vec3 = Vec3::new(0.36, 0.17, 0.09);
+const HIGHLAND_COLOR: Vec3 = Vec3::new(0.80, 0.55, 0.38);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportOptions {
+    pub height_path: PathBuf,
+    pub raw_size: Option<(u32, u32)>,
+    pub little_endian: bool,
+    pub color_path: Option<PathBuf>,
+    pub face_size: u32,
+    pub height_scale: f32,
+    pub output: PathBuf,
+}
+
+impl ImportOptions {
+    /// Parses the arguments following `IMPORT_COMMAND`.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ImportError> {
+        let mut height_path: Option<PathBuf> = None;
+        let mut options = ImportOptions {
+            height_path: PathBuf::new(),
+            raw_size: None,
+            little_endian: false,
+            color_path: None,
+            face_size: DEFAULT_FACE_SIZE,
+            height_scale: TerrainConfig::default().height_map_scale,
+            output: PathBuf::from(DEFAULT_OUTPUT),
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--little-endian" {
+                options.little_endian = true;
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| ImportError::Usage(format!("{} needs a value", arg)))?;
+            let invalid =
+                || ImportError::Usage(format!("{} is not a valid value for {}", value, arg));
+            match arg.as_str() {
+                "--height" => height_path = Some(PathBuf::from(&value)),
+                "--color" => options.color_path = Some(PathBuf::from(&value)),
+                "--out" => options.output = PathBuf::from(&value),
+                "--raw-size" => {
+                    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+                    let width: u32 = width.parse().map_err(|_| invalid())?;
+                    let height: u32 = height.parse().map_err(|_| invalid())?;
+                    if width == 0 || height == 0 {
+                        return Err(invalid());
+                    }
+                    options.raw_size = Some((width, height));
+                }
+                "--size" => {
+                    options.face_size = value.parse().map_err(|_| invalid())?;
+                    if options.face_size < 2 {
+                        return Err(invalid());
+                    }
+                }
+                "--height-scale" => {
+                    options.height_scale = value.parse().map_err(|_| invalid())?;
+                    if !options.height_scale.is_finite() {
+                        return Err(invalid());
+                    }
+                }
+                _ => return Err(ImportError::Usage(format!("Unknown option {}", arg))),
+            }
+        }
+
+        options.height_path =
+            height_path.ok_or_else(|| ImportError::Usage("--height is required".to_owned()))?;
+        return Ok(options);
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Usage(String),
+    Io(PathBuf, io::Error),
+    Image(PathBuf, image::ImageError),
+    RawSize(PathBuf, usize, usize),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ImportError::Usage(message) => write!(f, "{}\n\n{}", message, IMPORT_USAGE),
+            ImportError::Io(path, error) => write!(f, "Could not access {:?}: {}", path, error),
+            ImportError::Image(path, error) => write!(f, "Could not read {:?}: {}", path, error),
+            ImportError::RawSize(path, expected, actual) => write!(
+                f,
+                "Raw elevation map {:?} should hold {} bytes for its size but has {}",
+                path, expected, actual
+            ),
+        };
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// An equirectangular map with longitude along the width, starting at -180 degrees, and
+/// latitude down the height from the north pole, with any number of channels per texel.
+struct EquirectMap {
+    width: u32,
+    height: u32,
+    channels: usize,
+    values: Vec<f32>,
+}
+
+impl EquirectMap {
+    fn value(&self, x: i64, y: i64, channel: usize) -> f32 {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        return self.values[(y * self.width as usize + x) * self.channels + channel];
+    }
+
+    /// Bilinear sample of the map where a direction points, wrapping around in longitude.
+    fn sample(&self, direction: Vec3, channel: usize) -> f32 {
+        let (latitude, longitude) = cube_map::direction_to_lat_long(direction);
+        let x = (longitude + 180.0) / 360.0 * self.width as f32 - 0.5;
+        let y = (90.0 - latitude) / 180.0 * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = self.value(x0, y0, channel) * (1.0 - tx) + self.value(x0 + 1, y0, channel) * tx;
+        let bottom =
+            self.value(x0, y0 + 1, channel) * (1.0 - tx) + self.value(x0 + 1, y0 + 1, channel) * tx;
+        return top * (1.0 - ty) + bottom * ty;
+    }
+
+    fn range(&self) -> (f32, f32) {
+        return self
+            .values
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), value| {
+                (min.min(*value), max.max(*value))
+            });
+    }
+}
+
+fn load_height_map(options: &ImportOptions) -> Result<EquirectMap, ImportError> {
+    let path = &options.height_path;
+    let Some((width, height)) = options.raw_size else {
+        let image = image::open(path).map_err(|error| ImportError::Image(path.clone(), error))?;
+        return Ok(EquirectMap {
+            width: image.width(),
+            height: image.height(),
+            channels: 1,
+            values: image.to_luma32f().into_raw(),
+        });
+    };
+
+    let bytes = fs::read(path).map_err(|error| ImportError::Io(path.clone(), error))?;
+    let expected = width as usize * height as usize * 2;
+    if bytes.len() != expected {
+        return Err(ImportError::RawSize(path.clone(), expected, bytes.len()));
+    }
+    let values = bytes
+        .chunks_exact(2)
+        .map(|sample| {
+            let sample = [sample[0], sample[1]];
+            if options.little_endian {
+                i16::from_le_bytes(sample) as f32
+            } else {
+                i16::from_be_bytes(sample) as f32
+            }
+        })
+        .collect();
+    return Ok(EquirectMap {
+        width,
+        height,
+        channels: 1,
+        values,
+    });
+}
+
+fn load_color_map(path: &Path) -> Result<EquirectMap, ImportError> {
+    let image = image::open(path).map_err(|error| ImportError::Image(path.to_owned(), error))?;
+    return Ok(EquirectMap {
+        width: image.width(),
+        height: image.height(),
+        channels: 3,
+        values: image.to_rgb32f().into_raw(),
+    });
+}
+
+/// Writes the height, color and normal textures of all six faces below `options.output`.
+pub fn import_map(options: &ImportOptions) -> Result<(), ImportError> {
+    let height_map = load_height_map(options)?;
+    let color_map = match &options.color_path {
+        Some(path) => Some(load_color_map(path)?),
+        None => None,
+    };
+    let (lowest, highest) = height_map.range();
+    let height_range = (highest - lowest).max(f32::EPSILON);
+    println!(
+        "Elevation ranges from {} to {}, stored as 0 to 1 in the height faces",
+        lowest, highest
+    );
+
+    let size = options.face_size;
+    let directions = |face: usize| {
+        (0..size * size)
+            .map(move |i| cube_map::pixel_point(face, i % size, i / size, size).normalize())
+    };
+    let heights: Vec<Vec<f32>> = (0..FACE_NAMES.len())
+        .map(|face| {
+            directions(face)
+                .map(|direction| (height_map.sample(direction, 0) - lowest) / height_range)
+                .collect()
+        })
+        .collect();
+    let normals = normal_map::normal_faces(&heights, size, options.height_scale);
+
+    for (face, name) in FACE_NAMES.iter().enumerate() {
+        let height_image: ImageBuffer<Luma<u16>, Vec<u16>> = ImageBuffer::from_raw(
+            size,
+            size,
+            heights[face]
+                .iter()
+                .map(|height| (height.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16)
+                .collect(),
+        )
+        .expect("Face heights match the face size");
+
+        let mut color_image = RgbaImage::new(size, size);
+        for (i, direction) in directions(face).enumerate() {
+            let color = match &color_map {
+                Some(color_map) => Vec3::new(
+                    color_map.sample(direction, 0),
+                    color_map.sample(direction, 1),
+                    color_map.sample(direction, 2),
+                ),
+                None => LOWLAND_COLOR.lerp(HIGHLAND_COLOR, heights[face][i]),
+            };
+            let color = (color.clamp(Vec3::ZERO, Vec3::ONE) * 255.0).round();
+            color_image.put_pixel(
+                i as u32 % size,
+                i as u32 / size,
+                Rgba([color.x as u8, color.y as u8, color.z as u8, 255]),
+            );
+        }
+
+        save_face(&height_image, &options.output.join("height"), name)?;
+        save_face(&color_image, &options.output.join("color"), name)?;
+        save_face(&normals[face], &options.output.join("normal"), name)?;
+    }
+    println!(
+        "Wrote six {0}x{0} face textures to {1:?}",
+        size, options.output
+    );
+    return Ok(());
+}
+
+fn save_face<P, C>(
+    image: &ImageBuffer<P, C>,
+    directory: &Path,
+    name: &str,
+) -> Result<(), ImportError>
+where
+    P: image::PixelWithColorType,
+    [P::Subpixel]: image::EncodableLayout,
+    C: std::ops::Deref<Target = [P::Subpixel]>,
+{
+    fs::create_dir_all(directory).map_err(|error| ImportError::Io(directory.to_owned(), error))?;
+    let path = directory.join(format!("{}.png", name));
+    image
+        .save(&path)
+        .map_err(|error| ImportError::Image(path.clone(), error))?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        return values.iter().map(|value| value.to_string()).collect();
+    }
+
+    #[test]
+    fn parse_reads_raw_options() {
+        let options = ImportOptions::parse(args(&[
+            "--height",
+            "megdr.img",
+            "--raw-size",
+            "1440x720",
+            "--little-endian",
+            "--size",
+            "256",
+        ]))
+        .unwrap();
+        assert_eq!(options.height_path, PathBuf::from("megdr.img"));
+        assert_eq!(options.raw_size, Some((1440, 720)));
+        assert!(options.little_endian);
+        assert_eq!(options.face_size, 256);
+        assert_eq!(options.output, PathBuf::from(DEFAULT_OUTPUT));
+
+        assert!(ImportOptions::parse(args(&["--size", "256"])).is_err());
+        assert!(ImportOptions::parse(args(&["--height", "a.png", "--raw-size", "12"])).is_err());
+    }
+
+    #[test]
+    fn equirect_samples_follow_latitude_and_longitude() {
+        // Four columns at -135, -45, 45 and 135 degrees longitude, two rows for north and south
+        let map = EquirectMap {
+            width: 4,
+            height: 2,
+            channels: 1,
+            values: vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0],
+        };
+        let at = |latitude: f32, longitude: f32| {
+            let (latitude, longitude) = (latitude.to_radians(), longitude.to_radians());
+            // Inverse of `cube_map::direction_to_lat_long`
+            Vec3::new(
+                latitude.cos() * longitude.sin(),
+                latitude.sin(),
+                -latitude.cos() * longitude.cos(),
+            )
+        };
+        assert!((map.sample(at(45.0, -45.0), 0) - 1.0).abs() < 1e-4);
+        assert!((map.sample(at(-45.0, 135.0), 0) - 7.0).abs() < 1e-4);
+        // Halfway between the last and first column across the date line
+        assert!((map.sample(at(45.0, 180.0), 0) - 1.5).abs() < 1e-4);
+    }
+}
@@ -1,4 +1,4 @@
-use std::usize;
+use std::{collections::HashMap, sync::Arc};
 
 #[allow(unused_imports)]
 use bevy::{
@@ -13,19 +13,50 @@ use bevy::{
 
 use bevy_asset_loader::asset_collection::AssetCollection;
 use image::{DynamicImage, Rgb, RgbImage, RgbaImage};
+use rand::{rngs::StdRng, SeedableRng};
 
-use crate::{camera_system, game_assets};
+use crate::config_parser::{LodConfig, NoiseConfig, ProvincesConfig, TerrainConfig};
 
+mod craters;
+mod cube_map;
+mod kd_tree;
+mod lod;
+mod map_import;
 mod noise;
+mod normal_map;
 mod planet_material;
 mod planet_mesh;
 mod provinces;
+mod quadtree;
+mod terrain;
+
+pub use cube_map::face_index;
+pub use lod::PlanetLodPlugin;
+pub use map_import::{import_map, ImportOptions, IMPORT_COMMAND};
+pub use normal_map::generate_normal_maps;
+pub use planet_material::{ProvinceHighlight, NO_PROVINCE};
+pub use quadtree::{ChunkId, PlanetQuadtree};
+pub use terrain::{spawn_terrain_tasks, BuiltTerrain, TerrainMaps};
 
 #[derive(Asset, AssetCollection, Resource, TypePath, AsBindGroup, Debug, Clone)]
 pub struct PlanetMaterial {
     #[texture(100)]
     #[sampler(101)]
     pub border_texture: Option<Handle<Image>>,
+    #[texture(102, sample_type = "u_int")]
+    pub province_id_texture: Option<Handle<Image>>,
+    #[uniform(103)]
+    pub highlight: ProvinceHighlight,
+    #[uniform(104)]
+    pub map_mode: u32,
+    #[storage(105, read_only)]
+    pub owner_colors: Vec<Vec4>,
+}
+
+/// The normal map of every face, in `FACE_DIRECTIONS` order.
+#[derive(Resource, Debug)]
+pub struct NormalMaps {
+    pub faces: Vec<Handle<Image>>,
 }
 
 #[derive(Resource, Debug)]
@@ -34,51 +65,191 @@ pub struct BorderImages {
 }
 
 #[derive(Component)]
-pub struct PlanetEntity {
-    pub direction: String,
-}
+pub struct PlanetEntity;
 
-pub struct PlanetMesh {
+pub struct PlanetMesh<'a> {
+    chunk: ChunkId,
     resolution: u32,
     size: f32,
-    direction: Vec3,
-    height_map: Image,
+    skirt_depth: f32,
+    height_field: planet_mesh::HeightField<'a>,
 }
 
+pub type ProvinceId = u32;
+
+pub const MARS_RADIUS_KM: f32 = 3389.5;
+
 #[derive(Component)]
 pub struct Province {
-    pub id: i32,
+    pub id: ProvinceId,
     pub color: [u8; 3],
 }
 
-#[derive(AssetCollection, Resource)]
-pub struct MapImage {
-    pub image: RgbImage,
+/// Where a province sits on the planet. Latitudes and longitudes are in degrees, and a
+/// `min_longitude` greater than `max_longitude` means the province wraps over 180°.
+#[derive(Component, Debug, Clone)]
+pub struct ProvinceGeometry {
+    pub centroid: Vec3,
+    pub area_pixels: u32,
+    pub area_km2: f32,
+    pub min_latitude: f32,
+    pub max_latitude: f32,
+    pub min_longitude: f32,
+    pub max_longitude: f32,
+    pub faces: Vec<Vec3>,
+}
+
+/// Which provinces border each other, keyed by province id.
+/// Border lengths are counted in shared pixel edges of the province map.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct ProvinceGraph {
+    pub neighbors: HashMap<ProvinceId, HashMap<ProvinceId, u32>>,
+}
+
+impl ProvinceGraph {
+    pub fn add_border(&mut self, a: ProvinceId, b: ProvinceId) {
+        *self.neighbors.entry(a).or_default().entry(b).or_default() += 1;
+        *self.neighbors.entry(b).or_default().entry(a).or_default() += 1;
+    }
+
+    pub fn neighbors_of(&self, province: ProvinceId) -> impl Iterator<Item = ProvinceId> + '_ {
+        return self
+            .neighbors
+            .get(&province)
+            .into_iter()
+            .flat_map(|neighbors| neighbors.keys().copied());
+    }
+
+    pub fn border_length(&self, a: ProvinceId, b: ProvinceId) -> Option<u32> {
+        return self
+            .neighbors
+            .get(&a)
+            .and_then(|neighbors| neighbors.get(&b))
+            .copied();
+    }
+}
+
+/// Province ids for every pixel of every cube face, with faces stored in `FACE_DIRECTIONS`
+/// order and each face stored row by row.
+#[derive(Resource, Debug, Clone)]
+pub struct ProvinceMap {
+    pub dimensions: u32,
+    pub faces: Vec<Vec<ProvinceId>>,
 }
 
-#[derive(Resource)]
-pub struct PlanetLODs {
-    pub level_of_detail_meshes: Vec<(Vec3, String, Vec<Handle<Mesh>>)>,
+impl ProvinceMap {
+    /// Province under a direction from the planet center.
+    pub fn province_at(&self, direction: Vec3) -> Option<ProvinceId> {
+        if direction == Vec3::ZERO {
+            return None;
+        }
+        let (face, x, y) = cube_map::direction_to_pixel(direction, self.dimensions);
+        return self
+            .faces
+            .get(face)?
+            .get((y * self.dimensions + x) as usize)
+            .copied();
+    }
+
+    /// Checks that every face is complete and only names provinces below `province_count`,
+    /// since the ids index into textures and province tables. Maps read back from saves and
+    /// caches go through here before anything else touches them.
+    pub fn validate(&self, province_count: usize) -> Result<(), String> {
+        if self.dimensions == 0 {
+            return Err("dimensions are 0".to_owned());
+        }
+        if self.faces.len() != cube_map::FACE_DIRECTIONS.len() {
+            return Err(format!("{} faces instead of 6", self.faces.len()));
+        }
+        let face_length = (self.dimensions as usize).checked_mul(self.dimensions as usize);
+        for (face, ids) in self.faces.iter().enumerate() {
+            if Some(ids.len()) != face_length {
+                return Err(format!(
+                    "face {} does not cover {}x{} pixels",
+                    face, self.dimensions, self.dimensions
+                ));
+            }
+            if let Some(id) = ids.iter().find(|id| **id as usize >= province_count) {
+                return Err(format!(
+                    "face {} names province {} but there are {} provinces",
+                    face, id, province_count
+                ));
+            }
+        }
+        return Ok(());
+    }
 }
 
 pub async fn create_province_colors_async(
     num_provinces: u32,
     map_dimensions: u32,
+    seed: u64,
 ) -> Vec<(Rgb<u8>, u32, u32, u32)> {
-    return provinces::create_province_colors(num_provinces as usize, map_dimensions);
+    let mut rng = StdRng::seed_from_u64(seed);
+    return provinces::create_province_colors(num_provinces as usize, map_dimensions, &mut rng);
 }
 
 pub async fn create_province_images_async(
     colors: Vec<(Rgb<u8>, u32, u32, u32)>,
     map_dimensions: u32,
+    seed: u64,
+    provinces_config: ProvincesConfig,
+    noise_config: NoiseConfig,
+    height_maps: Arc<Vec<Image>>,
+    terrain_config: TerrainConfig,
 ) -> Vec<RgbImage> {
-    return provinces::create_provinces_images(colors, map_dimensions);
+    return provinces::create_provinces_images(
+        colors,
+        map_dimensions,
+        seed,
+        &provinces_config,
+        &noise_config,
+        height_maps,
+        &terrain_config,
+    )
+    .await;
 }
 
 pub async fn create_province_data_async(province_map: Vec<RgbImage>) -> Vec<Rgb<u8>> {
     return provinces::get_colors(&province_map);
 }
 
+pub async fn create_province_graph_async(
+    provinces_map: Vec<RgbImage>,
+    province_colors: Vec<Rgb<u8>>,
+) -> ProvinceGraph {
+    return provinces::get_province_graph(&provinces_map, &province_colors);
+}
+
+pub async fn create_province_geometry_async(
+    provinces_map: Vec<RgbImage>,
+    province_colors: Vec<Rgb<u8>>,
+) -> Vec<ProvinceGeometry> {
+    return provinces::get_province_geometry(&provinces_map, &province_colors);
+}
+
+pub async fn create_province_map_async(
+    provinces_map: Vec<RgbImage>,
+    province_colors: Vec<Rgb<u8>>,
+) -> ProvinceMap {
+    return provinces::get_province_map(&provinces_map, &province_colors);
+}
+
+/// Rebuilds everything that is derived from a finished province map, so a loaded save does
+/// not have to run the Voronoi generation again.
+pub fn derive_province_data(
+    province_map: &ProvinceMap,
+    province_colors: &[Rgb<u8>],
+) -> (BorderImages, ProvinceGraph, Vec<ProvinceGeometry>) {
+    let provinces_map = provinces::get_province_images(province_map, province_colors);
+    let border_images = BorderImages {
+        border_images: provinces::get_border_images(province_map.dimensions, &provinces_map),
+    };
+    let province_graph = provinces::get_province_graph(&provinces_map, province_colors);
+    let province_geometry = provinces::get_province_geometry(&provinces_map, province_colors);
+    return (border_images, province_graph, province_geometry);
+}
+
 pub async fn create_border_images_async(
     provinces_map: Vec<RgbImage>,
     map_dimensions: u32,
@@ -86,100 +257,188 @@ pub async fn create_border_images_async(
     return provinces::get_border_images(map_dimensions, &provinces_map);
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn setup(
     mut commands: Commands,
     mut planet_mats: ResMut<Assets<ExtendedMaterial<StandardMaterial, PlanetMaterial>>>,
-    planet_lods: Res<PlanetLODs>,
+    mut quadtree: ResMut<PlanetQuadtree>,
     border_images: Res<BorderImages>,
-    color_assets: Res<game_assets::ColorMapAssets>,
-    normal_assets: Res<game_assets::NormalMapAssets>,
+    province_map: Res<ProvinceMap>,
+    terrain_maps: Res<TerrainMaps>,
+    normal_maps: Res<NormalMaps>,
     asset_server: Res<AssetServer>,
 ) {
-    let directions = [
-        (Vec3::Y, "positive_y"),
-        (Vec3::NEG_Y, "negative_y"),
-        (Vec3::NEG_X, "negative_x"),
-        (Vec3::X, "positive_x"),
-        (Vec3::Z, "positive_z"),
-        (Vec3::NEG_Z, "negative_z"),
-    ];
-
-    for (_direction, suffix) in directions {
-        let color_handle = match suffix {
-            "positive_y" => color_assets.positive_y.clone(),
-            "negative_y" => color_assets.negative_y.clone(),
-            "negative_x" => color_assets.negative_x.clone(),
-            "positive_x" => color_assets.positive_x.clone(),
-            "positive_z" => color_assets.positive_z.clone(),
-            "negative_z" => color_assets.negative_z.clone(),
-            _ => continue,
-        };
-
-        let normal_handle = match suffix {
-            "positive_y" => normal_assets.positive_y.clone(),
-            "negative_y" => normal_assets.negative_y.clone(),
-            "negative_x" => normal_assets.negative_x.clone(),
-            "positive_x" => normal_assets.positive_x.clone(),
-            "positive_z" => normal_assets.positive_z.clone(),
-            "negative_z" => normal_assets.negative_z.clone(),
-            _ => continue,
-        };
-
-        let border_image = match suffix {
-            "positive_y" => border_images.border_images[5].clone(),
-            "negative_y" => border_images.border_images[4].clone(),
-            "negative_x" => border_images.border_images[1].clone(),
-            "positive_x" => border_images.border_images[0].clone(),
-            "positive_z" => border_images.border_images[3].clone(),
-            "negative_z" => border_images.border_images[2].clone(),
-            _ => continue,
-        };
+    for face in 0..cube_map::FACE_DIRECTIONS.len() {
+        let color_handle = terrain_maps.colors.get(face).cloned();
+        let normal_handle = normal_maps.faces.get(face).cloned();
+        let border_image = border_images.border_images[face].clone();
+        let province_id_image = planet_material::province_id_image(&province_map, face);
 
         let converted_border_image = bevy::render::texture::Image::from_dynamic(
-            DynamicImage::ImageRgba8(border_image.into()),
+            DynamicImage::ImageRgba8(border_image),
             false,
         );
-        let mut lod: Option<Handle<Mesh>> = None;
-        let mut dir: String = "".to_owned();
-        for (_direction, suffix_dir, lods) in planet_lods.level_of_detail_meshes.iter() {
-            if suffix_dir == suffix {
-                lod = Some(lods[0].clone());
-                dir = suffix_dir.to_owned();
-                break;
+        let material = planet_mats.add(ExtendedMaterial {
+            base: StandardMaterial {
+                base_color_texture: color_handle,
+                perceptual_roughness: 0.4,
+                normal_map_texture: normal_handle,
+                ..Default::default()
+            },
+            extension: PlanetMaterial {
+                border_texture: Some(asset_server.add(converted_border_image)),
+                province_id_texture: Some(asset_server.add(province_id_image.clone())),
+                highlight: ProvinceHighlight::default(),
+                map_mode: 0,
+                owner_colors: vec![Vec4::ZERO],
+            },
+        });
+        // The face only holds the material, its quadtree chunks are the meshes that get drawn
+        let face_entity = commands
+            .spawn((SpatialBundle::default(), material.clone(), PlanetEntity))
+            .id();
+        quadtree.attach_face(&mut commands, face, face_entity, material);
+    }
+}
+
+/// Builds the mesh of one chunk, `height_maps` holding every face in `FACE_DIRECTIONS` order
+/// so the edges can be sampled from the neighbouring faces.
+pub fn spawn_chunk(
+    chunk: ChunkId,
+    height_maps: &[Image],
+    lod_config: &LodConfig,
+    terrain_config: &TerrainConfig,
+) -> Mesh {
+    return planet_mesh::spawn_chunk(chunk, height_maps, lod_config, terrain_config);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::tasks::{AsyncComputeTaskPool, TaskPool};
+
+    fn solid_face_map(dimensions: u32) -> ProvinceMap {
+        let faces = (0..cube_map::FACE_DIRECTIONS.len())
+            .map(|face| vec![face as ProvinceId; (dimensions * dimensions) as usize])
+            .collect();
+        return ProvinceMap { dimensions, faces };
+    }
+
+    #[test]
+    fn province_at_face_centers() {
+        let province_map = solid_face_map(16);
+        for (face, direction) in cube_map::FACE_DIRECTIONS.iter().enumerate() {
+            assert_eq!(
+                province_map.province_at(*direction),
+                Some(face as ProvinceId)
+            );
+            assert_eq!(
+                province_map.province_at(*direction * 3.5),
+                Some(face as ProvinceId)
+            );
+        }
+    }
+
+    #[test]
+    fn province_at_face_centers_of_generated_map() {
+        let dimensions = 9;
+        let mut images: Vec<RgbImage> = Vec::new();
+        let mut colors: Vec<Rgb<u8>> = Vec::new();
+        for face in 0..cube_map::FACE_DIRECTIONS.len() {
+            let mut image = RgbImage::new(dimensions, dimensions);
+            for (x, y, pixel) in image.enumerate_pixels_mut() {
+                let color = Rgb([face as u8 + 1, x as u8, y as u8]);
+                *pixel = color;
+                colors.push(color);
             }
+            images.push(image);
         }
+        let province_map = provinces::get_province_map(&images, &colors);
 
-        if let Some(pulled_lod) = lod {
-            let planet = (
-                MaterialMeshBundle {
-                    mesh: pulled_lod,
-                    material: planet_mats.add(ExtendedMaterial {
-                        base: StandardMaterial {
-                            base_color_texture: Some(color_handle),
-                            perceptual_roughness: 0.4,
-                            normal_map_texture: Some(normal_handle),
-                            ..Default::default()
-                        },
-                        extension: PlanetMaterial {
-                            border_texture: Some(asset_server.add(converted_border_image)),
-                        },
-                    }),
-                    ..default()
-                },
-                /*
-                Wireframe,
-                WireframeColor {
-                    color: Color::BLACK,
-                },
-                */
-                camera_system::ThirdPersonCameraTarget,
-                PlanetEntity { direction: dir },
+        for (face, direction) in cube_map::FACE_DIRECTIONS.iter().enumerate() {
+            let center_color = Rgb([face as u8 + 1, 4, 4]);
+            let center_id = colors.iter().position(|color| *color == center_color);
+            assert_eq!(
+                province_map.province_at(*direction),
+                center_id.map(|id| id as ProvinceId)
             );
-            commands.spawn(planet);
         }
     }
-}
 
-pub fn spawn_face(direction: Vec3, height_map: &Image, resolution: u32) -> Mesh {
-    return planet_mesh::spawn_face(direction, height_map, resolution);
+    #[test]
+    fn faces_border_every_face_but_the_opposite_one() {
+        let dimensions = 8;
+        // Every face is a province of its own, so every border runs along a cube seam
+        let colors: Vec<Rgb<u8>> = (0..cube_map::FACE_DIRECTIONS.len())
+            .map(|face| Rgb([face as u8 + 1, 0, 0]))
+            .collect();
+        let images: Vec<RgbImage> = colors
+            .iter()
+            .map(|color| RgbImage::from_pixel(dimensions, dimensions, *color))
+            .collect();
+        let graph = provinces::get_province_graph(&images, &colors);
+
+        for (face, direction) in cube_map::FACE_DIRECTIONS.iter().enumerate() {
+            let mut neighbors: Vec<ProvinceId> = graph.neighbors_of(face as ProvinceId).collect();
+            neighbors.sort();
+            let expected: Vec<ProvinceId> = (0..cube_map::FACE_DIRECTIONS.len())
+                .filter(|other| cube_map::FACE_DIRECTIONS[*other].dot(*direction) == 0.0)
+                .map(|other| other as ProvinceId)
+                .collect();
+            assert_eq!(neighbors, expected, "face {}", face);
+            for neighbor in neighbors {
+                assert_eq!(
+                    graph.border_length(face as ProvinceId, neighbor),
+                    Some(dimensions)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn province_at_zero_direction() {
+        assert_eq!(solid_face_map(4).province_at(Vec3::ZERO), None);
+    }
+
+    fn generate_maps(seed: u64) -> (Vec<RgbImage>, Vec<RgbaImage>) {
+        AsyncComputeTaskPool::get_or_init(TaskPool::new);
+        let dimensions = 24;
+        return futures_lite::future::block_on(async {
+            let colors = create_province_colors_async(12, dimensions, seed).await;
+            let provinces_map = create_province_images_async(
+                colors,
+                dimensions,
+                seed,
+                ProvincesConfig::default(),
+                NoiseConfig::default(),
+                Arc::new(Vec::new()),
+                TerrainConfig::default(),
+            )
+            .await;
+            let border_images = create_border_images_async(provinces_map.clone(), dimensions).await;
+            return (provinces_map, border_images);
+        });
+    }
+
+    #[test]
+    fn same_seed_generates_identical_maps() {
+        let (provinces_map, border_images) = generate_maps(1337);
+        let (again_provinces, again_borders) = generate_maps(1337);
+        assert_eq!(provinces_map.len(), 6);
+        assert_eq!(border_images.len(), 6);
+        assert!(provinces_map
+            .iter()
+            .zip(&again_provinces)
+            .all(|(face, again)| face.as_raw() == again.as_raw()));
+        assert!(border_images
+            .iter()
+            .zip(&again_borders)
+            .all(|(face, again)| face.as_raw() == again.as_raw()));
+
+        let (other_provinces, _) = generate_maps(1338);
+        assert!(provinces_map
+            .iter()
+            .zip(&other_provinces)
+            .any(|(face, other)| face.as_raw() != other.as_raw()));
+    }
 }
@@ -1,66 +1,268 @@
 use bevy::math::Vec3;
 
-const GRID_SIZE: i32 = 400;
-
-pub fn make_perlin_noise(dimensions: u32) -> Vec<Vec<Vec<f64>>> {
-    let mut noise_map =
-        vec![vec![vec![0.0; dimensions as usize]; dimensions as usize]; dimensions as usize];
-
-    for x in 0..dimensions {
-        for y in 0..dimensions {
-            for z in 0..dimensions {
-                let mut val: f32 = 0.0;
-                let mut freq: f32 = 1.0;
-                let mut amp: f32 = 1.0;
-
-                for _ in 0..8 {
-                    val += perlin_3d(
-                        (x as f32 * freq) / GRID_SIZE as f32,
-                        (y as f32 * freq) / GRID_SIZE as f32,
-                        (z as f32 * freq) / GRID_SIZE as f32,
-                    ) * amp;
-                    freq *= 2.0;
-                    amp /= 2.0;
-                }
+use super::cube_map;
+use crate::config_parser::{NoiseConfig, NoiseKind};
+
+/// A scalar noise field over 3D space with values roughly in [-1, 1]. Implementations take
+/// points in lattice cells, `from_config` scales map cells down to those.
+pub trait NoiseSource: Send + Sync {
+    fn sample(&self, point: Vec3) -> f32;
+}
+
+impl NoiseSource for Box<dyn NoiseSource> {
+    fn sample(&self, point: Vec3) -> f32 {
+        return self.as_ref().sample(point);
+    }
+}
 
-                val *= 1.2;
-                if val > 1.0 {
-                    val = 1.0;
-                } else if val < -1.0 {
-                    val = -1.0;
+/// Builds the noise a config section describes. The result samples points in map cells and
+/// is scaled by `strength` and clamped to [-1, 1].
+pub fn from_config(seed: u64, config: &NoiseConfig) -> ConfiguredNoise {
+    let seed = fold_seed(seed);
+    let fbm = |source: Box<dyn NoiseSource>| -> Box<dyn NoiseSource> {
+        return Box::new(Fbm {
+            source,
+            octaves: config.octaves,
+            lacunarity: config.lacunarity,
+            gain: config.gain,
+        });
+    };
+    let source: Box<dyn NoiseSource> = match config.kind {
+        NoiseKind::Perlin => fbm(Box::new(Perlin { seed })),
+        NoiseKind::OpenSimplex => fbm(Box::new(OpenSimplex { seed })),
+        NoiseKind::Worley => fbm(Box::new(Worley { seed })),
+        NoiseKind::Ridged => Box::new(RidgedMultifractal {
+            source: Perlin { seed },
+            octaves: config.octaves,
+            lacunarity: config.lacunarity,
+            gain: config.gain,
+        }),
+        NoiseKind::DomainWarped => Box::new(DomainWarp {
+            source: fbm(Box::new(Perlin { seed })),
+            strength: config.warp_strength,
+        }),
+    };
+    return ConfiguredNoise {
+        source,
+        grid_size: config.grid_size,
+        strength: config.strength,
+    };
+}
+
+pub struct ConfiguredNoise {
+    source: Box<dyn NoiseSource>,
+    grid_size: f32,
+    strength: f32,
+}
+
+impl NoiseSource for ConfiguredNoise {
+    /// Noise in [-1, 1] at a point measured in map cells.
+    fn sample(&self, point: Vec3) -> f32 {
+        let value = self.source.sample(point / self.grid_size) * self.strength;
+        return value.clamp(-1.0, 1.0);
+    }
+}
+
+pub struct Perlin {
+    seed: u32,
+}
+
+impl NoiseSource for Perlin {
+    fn sample(&self, point: Vec3) -> f32 {
+        return perlin_3d(self.seed, point.x, point.y, point.z);
+    }
+}
+
+/// Gradient noise on the body-centred cubic lattice OpenSimplex2S uses, the cubic lattice
+/// plus a copy shifted by half a cell. Every lattice point within reach adds a smooth radial
+/// falloff times its gradient ramp.
+pub struct OpenSimplex {
+    seed: u32,
+}
+
+impl OpenSimplex {
+    const RADIUS_SQUARED: f32 = 0.75;
+    /// Brings the summed kernels out to roughly [-1, 1].
+    const NORMALIZATION: f32 = 9.0;
+}
+
+impl NoiseSource for OpenSimplex {
+    fn sample(&self, point: Vec3) -> f32 {
+        let mut value: f32 = 0.0;
+        for (lattice, offset) in [(0u32, Vec3::ZERO), (1, Vec3::splat(0.5))] {
+            let shifted = point - offset;
+            let base = shifted.floor();
+            // Only the corners of the containing cell are closer than the kernel radius
+            for corner in 0..8 {
+                let step = Vec3::new(
+                    (corner & 1) as f32,
+                    ((corner >> 1) & 1) as f32,
+                    ((corner >> 2) & 1) as f32,
+                );
+                let lattice_point = base + step;
+                let delta = shifted - lattice_point;
+                let falloff = Self::RADIUS_SQUARED - delta.length_squared();
+                if falloff <= 0.0 {
+                    continue;
                 }
+                let gradient = random_gradient(
+                    self.seed ^ lattice.wrapping_mul(0x9E37_79B9),
+                    lattice_point.x as i32,
+                    lattice_point.y as i32,
+                    lattice_point.z as i32,
+                );
+                let falloff = falloff * falloff;
+                value += falloff * falloff * gradient.dot(delta);
+            }
+        }
+        return value * Self::NORMALIZATION;
+    }
+}
+
+/// Cellular noise, -1 on the random feature point of each lattice cell rising to about 1
+/// furthest away from all of them.
+pub struct Worley {
+    seed: u32,
+}
 
-                noise_map[x as usize][y as usize][z as usize] = val as f64;
+impl NoiseSource for Worley {
+    fn sample(&self, point: Vec3) -> f32 {
+        let cell = point.floor();
+        let mut closest = f32::MAX;
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let neighbour = cell + Vec3::new(dx as f32, dy as f32, dz as f32);
+                    let (a, b, c) = hash_cell(
+                        self.seed,
+                        neighbour.x as i32,
+                        neighbour.y as i32,
+                        neighbour.z as i32,
+                    );
+                    let feature =
+                        neighbour + Vec3::new(a as f32, b as f32, c as f32) / u32::MAX as f32;
+                    closest = closest.min(feature.distance_squared(point));
+                }
             }
         }
+        return closest.sqrt() * 2.0 - 1.0;
     }
+}
 
-    return noise_map;
+/// Fractal Brownian motion, octaves of a source at rising frequency and falling amplitude.
+pub struct Fbm<S> {
+    source: S,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
 }
 
-fn random_gradient(ix: i32, iy: i32, iz: i32) -> Vec3 {
+impl<S: NoiseSource> NoiseSource for Fbm<S> {
+    fn sample(&self, point: Vec3) -> f32 {
+        let mut val: f32 = 0.0;
+        let mut freq: f32 = 1.0;
+        let mut amp: f32 = 1.0;
+
+        for _ in 0..self.octaves {
+            val += self.source.sample(point * freq) * amp;
+            freq *= self.lacunarity;
+            amp *= self.gain;
+        }
+        return val;
+    }
+}
+
+/// Musgrave's ridged multifractal. Each octave folds the source around zero into a ridge and
+/// is weighted by the octave before it, so finer detail only shows up along the crests.
+pub struct RidgedMultifractal<S> {
+    source: S,
+    octaves: u32,
+    lacunarity: f32,
+    gain: f32,
+}
+
+impl<S: NoiseSource> NoiseSource for RidgedMultifractal<S> {
+    fn sample(&self, point: Vec3) -> f32 {
+        let mut total: f32 = 0.0;
+        let mut total_amp: f32 = 0.0;
+        let mut freq: f32 = 1.0;
+        let mut amp: f32 = 1.0;
+        let mut weight: f32 = 1.0;
+
+        for _ in 0..self.octaves {
+            let ridge = 1.0 - self.source.sample(point * freq).abs();
+            let signal = ridge * ridge * weight;
+            weight = (signal * 2.0).clamp(0.0, 1.0);
+            total += signal * amp;
+            total_amp += amp;
+            freq *= self.lacunarity;
+            amp *= self.gain;
+        }
+        return total / total_amp.max(f32::EPSILON) * 2.0 - 1.0;
+    }
+}
+
+/// Samples a source at points pushed around by three decorrelated samples of itself.
+pub struct DomainWarp<S> {
+    source: S,
+    /// Largest displacement in lattice cells.
+    strength: f32,
+}
+
+impl<S: NoiseSource> NoiseSource for DomainWarp<S> {
+    fn sample(&self, point: Vec3) -> f32 {
+        // Arbitrary far apart offsets so the three displacement axes do not correlate
+        let offset = Vec3::new(
+            self.source.sample(point + Vec3::new(5.2, 1.3, 7.9)),
+            self.source.sample(point + Vec3::new(9.7, 2.8, 3.1)),
+            self.source.sample(point + Vec3::new(1.7, 8.4, 6.3)),
+        );
+        return self.source.sample(point + offset * self.strength);
+    }
+}
+
+/// Position of a face pixel centre in map cells, where the cube runs from 0 to
+/// `dimensions - 1` on every axis like the province seeds do.
+pub fn map_cell_point(face: usize, x: u32, y: u32, dimensions: u32) -> Vec3 {
+    let cube_point = cube_map::pixel_point(face, x, y, dimensions);
+    return (cube_point + 1.0) * 0.5 * (dimensions - 1) as f32;
+}
+
+/// Folds a 64 bit world seed down into the 32 bits used by the gradient hash.
+fn fold_seed(seed: u64) -> u32 {
+    return (seed ^ (seed >> 32)) as u32;
+}
+
+/// Hashes a lattice cell into three independent 32 bit values.
+fn hash_cell(seed: u32, ix: i32, iy: i32, iz: i32) -> (u32, u32, u32) {
     const W: u32 = 8 * std::mem::size_of::<u32>() as u32;
     const S: u32 = W / 2;
-    let mut a = ix as u32;
-    let mut b = iy as u32;
-    let mut c = iz as u32;
+    let mut a = (ix as u32).wrapping_add(seed.wrapping_mul(2654435769));
+    let mut b = (iy as u32) ^ seed;
+    let mut c = (iz as u32).wrapping_sub(seed.rotate_left(S));
 
     // Black magic from C, go see this reference:
     // https://www.pastebin.com/XwCPn0xR
     a = a.wrapping_mul(3284157443);
-    b ^= a << S | a >> W - S;
+    b ^= a << S | a >> (W - S);
     b = b.wrapping_mul(1911520717);
-    a ^= b << S | b >> W - S;
+    a ^= b << S | b >> (W - S);
     a = a.wrapping_mul(2048419325);
 
-    c ^= b << S | b >> W - S;
+    c ^= b << S | b >> (W - S);
     c = c.wrapping_mul(4294967197);
 
-    a ^= c << S | c >> W - S;
-    b ^= a << S | a >> W - S;
+    a ^= c << S | c >> (W - S);
+    b ^= a << S | a >> (W - S);
+
+    a ^= b << S | b >> (W - S);
 
-    a ^= b << S | b >> W - S;
+    return (a, b, c);
+}
 
+fn random_gradient(seed: u32, ix: i32, iy: i32, iz: i32) -> Vec3 {
+    const W: u32 = 8 * std::mem::size_of::<u32>() as u32;
+    let (a, b, c) = hash_cell(seed, ix, iy, iz);
     let random_vec = Vec3 {
         x: ((a % W) as f32) / (W as f32 / 2.0) - 1.0,
         y: ((b % W) as f32) / (W as f32 / 2.0) - 1.0,
@@ -70,6 +272,10 @@ fn random_gradient(ix: i32, iy: i32, iz: i32) -> Vec3 {
     let len =
         (random_vec.x * random_vec.x + random_vec.y * random_vec.y + random_vec.z * random_vec.z)
             .sqrt();
+    // The hash can land exactly on the origin, which has no direction to normalize
+    if len == 0.0 {
+        return Vec3::X;
+    }
     Vec3 {
         x: random_vec.x / len,
         y: random_vec.y / len,
@@ -77,8 +283,8 @@ fn random_gradient(ix: i32, iy: i32, iz: i32) -> Vec3 {
     }
 }
 
-fn dot_grid_gradient(ix: i32, iy: i32, iz: i32, x: f32, y: f32, z: f32) -> f32 {
-    let gradient: Vec3 = random_gradient(ix, iy, iz);
+fn dot_grid_gradient(seed: u32, ix: i32, iy: i32, iz: i32, x: f32, y: f32, z: f32) -> f32 {
+    let gradient: Vec3 = random_gradient(seed, ix, iy, iz);
     let dx: f32 = x - ix as f32;
     let dy: f32 = y - iy as f32;
     let dz: f32 = z - iz as f32;
@@ -89,7 +295,7 @@ fn interpolate(a0: f32, a1: f32, w: f32) -> f32 {
     return (a1 - a0) * (3.0 - w * 2.0) * w * w + a0;
 }
 
-fn perlin_3d(x: f32, y: f32, z: f32) -> f32 {
+fn perlin_3d(seed: u32, x: f32, y: f32, z: f32) -> f32 {
     let x0: i32 = x.floor() as i32;
     let x1: i32 = x0 + 1;
     let y0: i32 = y.floor() as i32;
@@ -101,22 +307,22 @@ fn perlin_3d(x: f32, y: f32, z: f32) -> f32 {
     let sy: f32 = y - y0 as f32;
     let sz: f32 = z - z0 as f32;
 
-    let n0: f32 = dot_grid_gradient(x0, y0, z0, x, y, z);
-    let n1: f32 = dot_grid_gradient(x1, y0, z0, x, y, z);
+    let n0: f32 = dot_grid_gradient(seed, x0, y0, z0, x, y, z);
+    let n1: f32 = dot_grid_gradient(seed, x1, y0, z0, x, y, z);
     let ix0: f32 = interpolate(n0, n1, sx);
 
-    let n2: f32 = dot_grid_gradient(x0, y1, z0, x, y, z);
-    let n3: f32 = dot_grid_gradient(x1, y1, z0, x, y, z);
+    let n2: f32 = dot_grid_gradient(seed, x0, y1, z0, x, y, z);
+    let n3: f32 = dot_grid_gradient(seed, x1, y1, z0, x, y, z);
     let ix1: f32 = interpolate(n2, n3, sx);
 
     let iy0: f32 = interpolate(ix0, ix1, sy);
 
-    let n4: f32 = dot_grid_gradient(x0, y0, z1, x, y, z);
-    let n5: f32 = dot_grid_gradient(x1, y0, z1, x, y, z);
+    let n4: f32 = dot_grid_gradient(seed, x0, y0, z1, x, y, z);
+    let n5: f32 = dot_grid_gradient(seed, x1, y0, z1, x, y, z);
     let ix2: f32 = interpolate(n4, n5, sx);
 
-    let n6: f32 = dot_grid_gradient(x0, y1, z1, x, y, z);
-    let n7: f32 = dot_grid_gradient(x1, y1, z1, x, y, z);
+    let n6: f32 = dot_grid_gradient(seed, x0, y1, z1, x, y, z);
+    let n7: f32 = dot_grid_gradient(seed, x1, y1, z1, x, y, z);
     let ix3: f32 = interpolate(n6, n7, sx);
 
     let iy1: f32 = interpolate(ix2, ix3, sy);
@@ -124,3 +330,74 @@ fn perlin_3d(x: f32, y: f32, z: f32) -> f32 {
     let val: f32 = interpolate(iy0, iy1, sz);
     return val;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [NoiseKind; 5] = [
+        NoiseKind::Perlin,
+        NoiseKind::OpenSimplex,
+        NoiseKind::Worley,
+        NoiseKind::Ridged,
+        NoiseKind::DomainWarped,
+    ];
+
+    #[test]
+    fn every_kind_is_finite_repeatable_and_varied() {
+        let dimensions = 24;
+        for kind in KINDS {
+            let config = NoiseConfig {
+                kind,
+                grid_size: 8.0,
+                ..NoiseConfig::default()
+            };
+            let noise = from_config(1337, &config);
+            let (mut lowest, mut highest) = (f32::MAX, f32::MIN);
+            for face in 0..cube_map::FACE_DIRECTIONS.len() {
+                for y in 0..dimensions {
+                    for x in 0..dimensions {
+                        let point = map_cell_point(face, x, y, dimensions);
+                        let value = noise.sample(point);
+                        assert!(value.is_finite() && (-1.0..=1.0).contains(&value));
+                        assert_eq!(value, noise.sample(point));
+                        lowest = lowest.min(value);
+                        highest = highest.max(value);
+                    }
+                }
+            }
+            assert!(highest - lowest > 0.5, "{:?} noise is nearly flat", kind);
+            // Lattice points themselves have to be well defined too
+            for i in 0..64 {
+                let point = Vec3::splat(i as f32 * config.grid_size);
+                assert!(noise.sample(point).is_finite());
+            }
+        }
+    }
+
+    #[test]
+    fn open_simplex_stays_in_range() {
+        let noise = OpenSimplex { seed: 7 };
+        let mut highest: f32 = 0.0;
+        for i in 0..20000 {
+            let point = Vec3::new(i as f32 * 0.137, i as f32 * 0.071, i as f32 * 0.029);
+            highest = highest.max(noise.sample(point).abs());
+        }
+        assert!(highest > 0.5 && highest <= 1.0, "peak is {}", highest);
+    }
+
+    #[test]
+    fn different_seeds_give_different_noise() {
+        let point = Vec3::new(123.4, 56.7, 89.0);
+        for kind in KINDS {
+            let config = NoiseConfig {
+                kind,
+                ..NoiseConfig::default()
+            };
+            assert_ne!(
+                from_config(1, &config).sample(point),
+                from_config(2, &config).sample(point)
+            );
+        }
+    }
+}
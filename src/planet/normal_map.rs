@@ -0,0 +1,122 @@
+use bevy::prelude::*;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use super::{cube_map, planet_mesh};
+use crate::config_parser::{HeightSampling, TerrainConfig};
+
+/// Tangent-space normal maps for the six cube faces from their heights, stored row by row
+/// per face in `FACE_DIRECTIONS` order with values in [0, 1]. Gradients at the border step
+/// over the seam onto the neighbouring face, so the lighting has no creases along the edges.
+/// Green follows the glTF convention of pointing up the image.
+pub fn normal_faces(heights: &[Vec<f32>], dimensions: u32, height_scale: f32) -> Vec<RgbaImage> {
+    let height_at = |face: usize, x: u32, y: u32| -> f32 {
+        return heights
+            .get(face)
+            .and_then(|face_heights| face_heights.get((y * dimensions + x) as usize))
+            .copied()
+            .unwrap_or(0.0);
+    };
+    // Both central differences span two texels of a face that is 2 wide on the unit cube
+    let slope_scale = height_scale * dimensions as f32 / 4.0;
+
+    return (0..heights.len())
+        .map(|face| {
+            return RgbaImage::from_fn(dimensions, dimensions, |x, y| {
+                let step = |dx: i32, dy: i32| {
+                    let (face, x, y) = cube_map::neighbor_pixel(face, x, y, dx, dy, dimensions);
+                    height_at(face, x, y)
+                };
+                let along_u = (step(1, 0) - step(-1, 0)) * slope_scale;
+                let along_v = (step(0, 1) - step(0, -1)) * slope_scale;
+                let normal = Vec3::new(-along_u, along_v, 1.0).normalize();
+                let encoded = (normal * 0.5 + 0.5) * 255.0;
+                Rgba([
+                    encoded.x.round() as u8,
+                    encoded.y.round() as u8,
+                    encoded.z.round() as u8,
+                    255,
+                ])
+            });
+        })
+        .collect();
+}
+
+/// Normal map textures for the height map images of the six faces, at the size of the first
+/// face. Faces of other sizes are resampled to match.
+pub fn generate_normal_maps(height_maps: &[Image], terrain_config: &TerrainConfig) -> Vec<Image> {
+    let dimensions = height_maps
+        .first()
+        .map_or(2, |height_map| height_map.texture_descriptor.size.width)
+        .max(2);
+    let heights: Vec<Vec<f32>> = height_maps
+        .iter()
+        .map(|height_map| {
+            (0..dimensions * dimensions)
+                .map(|i| {
+                    let uv = Vec2::new(
+                        ((i % dimensions) as f32 + 0.5) / dimensions as f32,
+                        ((i / dimensions) as f32 + 0.5) / dimensions as f32,
+                    );
+                    // Texel centres come back unfiltered when the sizes match
+                    planet_mesh::sample_height_map(uv, height_map, HeightSampling::Bilinear)
+                })
+                .collect()
+        })
+        .collect();
+    return normal_faces(&heights, dimensions, terrain_config.height_map_scale)
+        .into_iter()
+        .map(|normal_map| Image::from_dynamic(DynamicImage::ImageRgba8(normal_map), false))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_faces_point_straight_out() {
+        let dimensions = 4;
+        let heights = vec![vec![0.5; (dimensions * dimensions) as usize]; 6];
+        for face in normal_faces(&heights, dimensions, 0.25) {
+            for pixel in face.pixels() {
+                assert_eq!(*pixel, Rgba([128, 128, 255, 255]));
+            }
+        }
+    }
+
+    #[test]
+    fn slopes_lean_away_from_higher_ground() {
+        let dimensions = 8;
+        // Heights rising along +u on every face
+        let ramp: Vec<f32> = (0..dimensions * dimensions)
+            .map(|i| (i % dimensions) as f32 / dimensions as f32)
+            .collect();
+        let faces = normal_faces(&vec![ramp; 6], dimensions, 0.25);
+        let centre = faces[0].get_pixel(dimensions / 2, dimensions / 2);
+        assert!(centre[0] < 128);
+        assert_eq!(centre[1], 128);
+    }
+
+    #[test]
+    fn gradients_carry_over_face_edges() {
+        let dimensions = 16;
+        // A smooth slope around the planet, rising towards +Z
+        let heights: Vec<Vec<f32>> = (0..cube_map::FACE_DIRECTIONS.len())
+            .map(|face| {
+                (0..dimensions * dimensions)
+                    .map(|i| {
+                        let point =
+                            cube_map::pixel_point(face, i % dimensions, i / dimensions, dimensions);
+                        point.normalize().z * 0.5 + 0.5
+                    })
+                    .collect()
+            })
+            .collect();
+        let faces = normal_faces(&heights, dimensions, 0.25);
+        // u runs along +Z on the +X face, so its last column borders the +Z face
+        let row = dimensions / 2;
+        let edge = faces[0].get_pixel(dimensions - 1, row);
+        let inner = faces[0].get_pixel(dimensions - 2, row);
+        assert!((edge[0] as i32 - inner[0] as i32).abs() <= 2);
+    }
+}
@@ -1,5 +1,44 @@
 use crate::planet;
-use bevy::{pbr::MaterialExtension, render::render_resource::ShaderRef};
+use bevy::{
+    pbr::MaterialExtension,
+    prelude::*,
+    render::render_resource::{Extent3d, ShaderRef, TextureDimension, TextureFormat},
+};
+
+/// Marks a highlight slot in `ProvinceHighlight` as unused.
+pub const NO_PROVINCE: u32 = u32::MAX;
+
+pub use highlight::ProvinceHighlight;
+
+// The ShaderType derive emits a never called trait check function per field, outside the
+// reach of attributes on the struct itself, so the allow is scoped to a module of its own
+#[allow(dead_code)]
+mod highlight {
+    use bevy::{prelude::Vec4, render::render_resource::ShaderType};
+
+    /// Which provinces the planet shader tints and outlines, compared against the province id
+    /// texture so highlighting never touches the border images.
+    #[derive(ShaderType, Debug, Clone, Copy)]
+    pub struct ProvinceHighlight {
+        pub hovered_color: Vec4,
+        pub selected_color: Vec4,
+        pub hovered_province: u32,
+        pub selected_province: u32,
+        pub outline_width: f32,
+    }
+}
+
+impl Default for ProvinceHighlight {
+    fn default() -> Self {
+        ProvinceHighlight {
+            hovered_color: Vec4::new(1.0, 1.0, 1.0, 0.25),
+            selected_color: Vec4::new(1.0, 0.8, 0.3, 1.0),
+            hovered_province: NO_PROVINCE,
+            selected_province: NO_PROVINCE,
+            outline_width: 3.0,
+        }
+    }
+}
 
 impl MaterialExtension for planet::PlanetMaterial {
     fn fragment_shader() -> ShaderRef {
@@ -10,3 +49,21 @@ impl MaterialExtension for planet::PlanetMaterial {
         "shaders/planet/planet.wgsl".into()
     }
 }
+
+/// Packs one face of the province map into an unfiltered `R32Uint` texture for the shader.
+pub fn province_id_image(province_map: &planet::ProvinceMap, face: usize) -> Image {
+    let data = province_map.faces[face]
+        .iter()
+        .flat_map(|province_id| province_id.to_le_bytes())
+        .collect::<Vec<u8>>();
+    return Image::new(
+        Extent3d {
+            width: province_map.dimensions,
+            height: province_map.dimensions,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::R32Uint,
+    );
+}
@@ -1,100 +1,177 @@
 use bevy::{
-    math::Vec3Swizzles,
     prelude::*,
-    render::{mesh::Indices, render_resource::PrimitiveTopology},
+    render::{
+        mesh::Indices,
+        render_resource::{PrimitiveTopology, TextureFormat},
+    },
 };
 
-use crate::planet;
+use super::{cube_map, quadtree::ChunkId};
+use crate::{
+    config_parser::{HeightSampling, LodConfig, TerrainConfig},
+    planet,
+};
+
+/// The planet surface as a function of direction, sampling whichever face image a direction
+/// points through. Neighbouring faces therefore agree on heights and normals along seams.
+#[derive(Clone, Copy)]
+pub struct HeightField<'a> {
+    /// One height map per face, in `cube_map::FACE_DIRECTIONS` order.
+    height_maps: &'a [Image],
+    height_map_scale: f32,
+    sampling: HeightSampling,
+}
+
+impl<'a> HeightField<'a> {
+    pub fn new(height_maps: &'a [Image], terrain_config: &TerrainConfig) -> Self {
+        return HeightField {
+            height_maps,
+            height_map_scale: terrain_config.height_map_scale,
+            sampling: terrain_config.height_sampling,
+        };
+    }
 
-const HEIGHT_MAP_SCALE: f32 = 0.25;
+    pub fn height(&self, direction: Vec3) -> f32 {
+        let (face, uv) = cube_map::direction_to_face_uv(direction);
+        return match self.height_maps.get(face) {
+            Some(height_map) => sample_height_map(uv, height_map, self.sampling),
+            None => 0.0,
+        };
+    }
+
+    /// The displaced surface point above a unit direction, for a planet of the given size.
+    pub fn surface_point(&self, direction: Vec3, size: f32) -> Vec3 {
+        return direction * (size + self.height(direction) * self.height_map_scale);
+    }
+
+    /// Spacing of the height map texels on the unit cube, the finest detail worth sampling.
+    pub fn texel_step(&self) -> f32 {
+        let width = self
+            .height_maps
+            .first()
+            .map_or(1, |height_map| height_map.texture_descriptor.size.width);
+        return 2.0 / width.max(1) as f32;
+    }
 
-pub fn spawn_face(direction: Vec3, height_map: &Image, resolution: u32) -> Mesh {
+    /// Normal of the displaced surface from central differences of the height field.
+    /// Depending only on the direction, it agrees across faces and quadtree depths.
+    pub fn surface_normal(&self, direction: Vec3, size: f32, step: f32) -> Vec3 {
+        let (tangent, bitangent) = direction.any_orthonormal_pair();
+        let point = |offset: Vec3| self.surface_point((direction + offset).normalize(), size);
+        let along_tangent = point(tangent * step) - point(-tangent * step);
+        let along_bitangent = point(bitangent * step) - point(-bitangent * step);
+        let normal = along_tangent.cross(along_bitangent).normalize();
+        if normal.dot(direction) < 0.0 {
+            return -normal;
+        }
+        return normal;
+    }
+}
+
+pub fn spawn_chunk(
+    chunk: ChunkId,
+    height_maps: &[Image],
+    lod_config: &LodConfig,
+    terrain_config: &TerrainConfig,
+) -> Mesh {
     return Mesh::from(planet::PlanetMesh {
-        resolution: resolution,
+        chunk,
+        resolution: lod_config.chunk_resolution,
         size: 1.0,
-        direction: direction,
-        height_map: height_map.clone(),
+        skirt_depth: lod_config.skirt_depth * chunk.edge_length(),
+        height_field: HeightField::new(height_maps, terrain_config),
     })
     .with_generated_tangents()
     .unwrap();
 }
 
-impl From<planet::PlanetMesh> for Mesh {
+impl From<planet::PlanetMesh<'_>> for Mesh {
     fn from(planet: planet::PlanetMesh) -> Self {
-        let (vert_lists, triangle_lists): (Vec<Vec<Vec3>>, Vec<Vec<u32>>) =
-            face(planet.resolution, planet.direction, planet.size);
-
-        let vertices = vert_lists
+        let (mut directions, mut uvs, mut triangle_list) =
+            chunk_grid(planet.chunk, planet.resolution);
+        let normal_step = planet.height_field.texel_step();
+        let mut positions = directions
             .iter()
-            .flat_map(|v| v.iter().map(|v| [v.x, v.y, v.z]))
-            .collect::<Vec<[f32; 3]>>();
+            .map(|direction| planet.height_field.surface_point(*direction, planet.size))
+            .collect::<Vec<Vec3>>();
 
-        let triangle_list = triangle_lists
-            .iter()
-            .enumerate()
-            .flat_map(|(face_id, list)| {
-                list.iter().map(move |local_idx| {
-                    let num_indices = planet.resolution * planet.resolution;
-                    local_idx + face_id as u32 * num_indices
-                })
-            })
-            .collect::<Vec<u32>>();
-
-        let mut uvs: Vec<[f32; 2]> =
-            Vec::with_capacity((planet.resolution * planet.resolution) as usize);
-        for y in 0..planet.resolution {
-            for x in 0..planet.resolution {
-                let u = x as f32 / (planet.resolution - 1) as f32;
-                let v = y as f32 / (planet.resolution - 1) as f32;
-                uvs.push([u, v]);
-            }
+        // Skirts hang from the chunk border into the planet and cover the cracks left where a
+        // neighbouring chunk of another depth has fewer or more vertices along the shared edge
+        let border = border_indices(planet.resolution);
+        let skirt_start = directions.len() as u32;
+        for (k, &edge_index) in border.iter().enumerate() {
+            let direction = directions[edge_index as usize];
+            positions.push(positions[edge_index as usize] - direction * planet.skirt_depth);
+            uvs.push(uvs[edge_index as usize]);
+            directions.push(direction);
+
+            let next_edge_index = border[(k + 1) % border.len()];
+            let skirt_index = skirt_start + k as u32;
+            let next_skirt_index = skirt_start + ((k + 1) % border.len()) as u32;
+            // Both windings, so the skirt shows whichever side the camera looks at it from
+            triangle_list.extend_from_slice(&[
+                edge_index,
+                next_edge_index,
+                skirt_index,
+                next_edge_index,
+                next_skirt_index,
+                skirt_index,
+                edge_index,
+                skirt_index,
+                next_edge_index,
+                next_edge_index,
+                skirt_index,
+                next_skirt_index,
+            ]);
         }
 
-        let deformed_vertices = vertices
+        let normals = directions
             .iter()
-            .zip(vertices.iter())
-            .zip(uvs.iter())
-            .map(|((&vertex, &normal), &uv)| {
-                deform_with_heightmap(
-                    &vertex.into(),
-                    &normal.into(),
-                    &uv.into(),
-                    planet.height_map.clone().into(),
-                )
+            .map(|direction| {
+                planet
+                    .height_field
+                    .surface_normal(*direction, planet.size, normal_step)
             })
             .collect::<Vec<Vec3>>();
 
-        let normals = compute_vertex_normals(&deformed_vertices, &triangle_list);
-
         let mut mesh: Mesh = Mesh::new(PrimitiveTopology::TriangleList);
-        mesh.set_indices(Some(Indices::U32(triangle_list.clone())));
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, deformed_vertices.clone());
-        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals.clone());
+        mesh.set_indices(Some(Indices::U32(triangle_list)));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
         mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
 
         return mesh;
     }
 }
 
-fn face(resolution: u32, local_up: Vec3, size: f32) -> (Vec<Vec<Vec3>>, Vec<Vec<u32>>) {
-    let axis_a = local_up.yzx();
-    let axis_b = local_up.cross(axis_a);
+/// Unit directions, face uvs and triangles of a chunk grid. The cube coordinates are built
+/// from integer numerators over the whole face, so vertices on a shared edge come out bit for
+/// bit identical on both sides, whichever face or depth each neighbour belongs to.
+fn chunk_grid(chunk: ChunkId, resolution: u32) -> (Vec<Vec3>, Vec<[f32; 2]>, Vec<u32>) {
+    let local_up = cube_map::FACE_DIRECTIONS[chunk.face];
+    let (axis_a, axis_b) = cube_map::face_axes(local_up);
+    let chunk_segments = resolution as i64 - 1;
+    let face_segments = chunk_segments << chunk.depth;
 
-    let mut vertices = Vec::with_capacity(resolution as usize * resolution as usize);
+    let mut directions = Vec::with_capacity(resolution as usize * resolution as usize);
+    let mut uvs = Vec::with_capacity(resolution as usize * resolution as usize);
     let mut triangles =
         Vec::with_capacity((resolution as usize - 1) * (resolution as usize - 1) * 6);
 
     for y in 0..resolution {
         for x in 0..resolution {
             let i = x + y * resolution;
-            let percent_x = x as f32 / (resolution - 1) as f32;
-            let percent_y = y as f32 / (resolution - 1) as f32;
-
-            let point_on_unit_cube: Vec3 =
-                local_up + (percent_x - 0.5) * 2.0 * axis_a + (percent_y - 0.5) * 2.0 * axis_b;
-            let point_on_unit_sphere: Vec3 = point_on_unit_cube.normalize() * size;
+            let face_x = chunk.x as i64 * chunk_segments + x as i64;
+            let face_y = chunk.y as i64 * chunk_segments + y as i64;
+            let cube_x = (2 * face_x - face_segments) as f32 / face_segments as f32;
+            let cube_y = (2 * face_y - face_segments) as f32 / face_segments as f32;
 
-            vertices.push(point_on_unit_sphere);
+            let point_on_unit_cube: Vec3 = local_up + cube_x * axis_a + cube_y * axis_b;
+            directions.push(point_on_unit_cube.normalize());
+            uvs.push([
+                face_x as f32 / face_segments as f32,
+                face_y as f32 / face_segments as f32,
+            ]);
 
             if x != resolution - 1 && y != resolution - 1 {
                 triangles.push(i);
@@ -107,58 +184,368 @@ fn face(resolution: u32, local_up: Vec3, size: f32) -> (Vec<Vec<Vec3>>, Vec<Vec<
             }
         }
     }
-    (vec![vertices], vec![triangles])
+    return (directions, uvs, triangles);
 }
 
-fn sample_height_map(uv: Vec2, height_map: &Image) -> f32 {
-    let width = height_map.texture_descriptor.size.width as f32;
-    let height = height_map.texture_descriptor.size.height as f32;
+/// Grid indices around the border of a chunk, walked once in order.
+fn border_indices(resolution: u32) -> Vec<u32> {
+    let last = resolution - 1;
+    let mut border: Vec<u32> = Vec::with_capacity(4 * last as usize);
+    border.extend(0..last);
+    border.extend((0..last).map(|y| last + y * resolution));
+    border.extend((0..last).map(|x| (last - x) + last * resolution));
+    border.extend((0..last).map(|y| (last - y) * resolution));
+    return border;
+}
 
-    let x_pos = (uv.x * width).clamp(0.0, width - 1.0) as usize;
-    let y_pos = (uv.y * height).clamp(0.0, height - 1.0) as usize;
-    let buffer_pos = (y_pos * width as usize + x_pos) * 4; // *4 for RGBA even if we only use R
+/// Height at a face uv in [0, 1], filtered between texel centres.
+pub(super) fn sample_height_map(uv: Vec2, height_map: &Image, sampling: HeightSampling) -> f32 {
+    let size = height_map.texture_descriptor.size;
+    if size.width == 0 || size.height == 0 {
+        return 0.0;
+    }
+    let x = uv.x * size.width as f32 - 0.5;
+    let y = uv.y * size.height as f32 - 0.5;
 
-    if buffer_pos < height_map.data.len() {
-        let height_value = height_map.data[buffer_pos];
-        height_value as f32 / 255.0
-    } else {
-        0.0
+    match sampling {
+        HeightSampling::Nearest => {
+            return texel_height(height_map, x.round() as i64, y.round() as i64);
+        }
+        HeightSampling::Bilinear => {
+            let (x0, y0) = (x.floor(), y.floor());
+            let (tx, ty) = (x - x0, y - y0);
+            let (x0, y0) = (x0 as i64, y0 as i64);
+            let top = lerp(
+                texel_height(height_map, x0, y0),
+                texel_height(height_map, x0 + 1, y0),
+                tx,
+            );
+            let bottom = lerp(
+                texel_height(height_map, x0, y0 + 1),
+                texel_height(height_map, x0 + 1, y0 + 1),
+                tx,
+            );
+            return lerp(top, bottom, ty);
+        }
+        HeightSampling::Bicubic => {
+            let (x0, y0) = (x.floor(), y.floor());
+            let (tx, ty) = (x - x0, y - y0);
+            let (x0, y0) = (x0 as i64, y0 as i64);
+            let row = |texel_y: i64| {
+                let heights =
+                    [-1, 0, 1, 2].map(|offset| texel_height(height_map, x0 + offset, texel_y));
+                catmull_rom(heights, tx)
+            };
+            return catmull_rom([row(y0 - 1), row(y0), row(y0 + 1), row(y0 + 2)], ty);
+        }
     }
 }
 
-fn deform_with_heightmap(vertex: &Vec3, normal: &Vec3, uv: &Vec2, height_map: Image) -> Vec3 {
-    let height_sample = sample_height_map(*uv, &height_map);
-    return *vertex + *normal * (height_sample * HEIGHT_MAP_SCALE);
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    return a + (b - a) * t;
+}
+
+/// Cubic through `points[1]` at `t = 0` and `points[2]` at `t = 1`, with slopes taken from
+/// the outer points so neighbouring spans join smoothly.
+fn catmull_rom(points: [f32; 4], t: f32) -> f32 {
+    let [p0, p1, p2, p3] = points;
+    return 0.5
+        * (2.0 * p1
+            + (p2 - p0) * t
+            + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t
+            + (3.0 * (p1 - p2) + p3 - p0) * t * t * t);
+}
+
+/// Height of one texel in [0, 1] from the first channel of the image, clamping coordinates
+/// to the border. 8 bit, 16 bit and 32 bit float images are read at their full precision,
+/// other formats count as flat.
+pub(super) fn texel_height(height_map: &Image, x: i64, y: i64) -> f32 {
+    let size = height_map.texture_descriptor.size;
+    let x = x.clamp(0, size.width as i64 - 1) as usize;
+    let y = y.clamp(0, size.height as i64 - 1) as usize;
+
+    let (pixel_size, read): (usize, fn(&[u8]) -> f32) = match height_map.texture_descriptor.format {
+        TextureFormat::R8Unorm => (1, |bytes| bytes[0] as f32 / 255.0),
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {
+            (4, |bytes| bytes[0] as f32 / 255.0)
+        }
+        // Bevy loads 16 bit grayscale PNGs as R16Uint, the values are still normalized heights
+        TextureFormat::R16Unorm | TextureFormat::R16Uint => (2, read_u16_height),
+        TextureFormat::Rg16Uint => (4, read_u16_height),
+        TextureFormat::Rgba16Unorm => (8, read_u16_height),
+        TextureFormat::R32Float => (4, read_f32_height),
+        TextureFormat::Rgba32Float => (16, read_f32_height),
+        _ => return 0.0,
+    };
+    let offset = (y * size.width as usize + x) * pixel_size;
+    return match height_map.data.get(offset..offset + pixel_size) {
+        Some(bytes) => read(bytes),
+        None => 0.0,
+    };
+}
+
+fn read_u16_height(bytes: &[u8]) -> f32 {
+    return u16::from_ne_bytes([bytes[0], bytes[1]]) as f32 / u16::MAX as f32;
 }
 
-fn compute_triangle_normal(p0: Vec3, p1: Vec3, p2: Vec3) -> Vec3 {
-    let v0 = p1 - p0;
-    let v1 = p2 - p0;
-    let normal = v0.cross(v1).normalize();
-    return normal;
+fn read_f32_height(bytes: &[u8]) -> f32 {
+    return f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
 }
 
-fn compute_vertex_normals(vertices: &Vec<Vec3>, triangles: &Vec<u32>) -> Vec<Vec3> {
-    let mut normals = vec![Vec3::ZERO; vertices.len()];
-    let mut face_normals = Vec::with_capacity(triangles.len() / 3);
+#[cfg(test)]
+mod tests {
+    use bevy::render::{
+        mesh::VertexAttributeValues,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    };
 
-    for triangle in triangles.chunks(3) {
-        let normal = compute_triangle_normal(
-            vertices[triangle[0] as usize],
-            vertices[triangle[1] as usize],
-            vertices[triangle[2] as usize],
+    use super::*;
+
+    /// Six height maps that all differ from each other, so a seam sampling the wrong face shows.
+    fn test_height_maps(dimensions: u32) -> Vec<Image> {
+        return (0..cube_map::FACE_DIRECTIONS.len())
+            .map(|face| {
+                let mut data: Vec<u8> = Vec::with_capacity((dimensions * dimensions * 4) as usize);
+                for y in 0..dimensions {
+                    for x in 0..dimensions {
+                        let value = ((x * 37 + y * 11 + face as u32 * 53) % 256) as u8;
+                        data.extend_from_slice(&[value, value, value, 255]);
+                    }
+                }
+                Image::new(
+                    Extent3d {
+                        width: dimensions,
+                        height: dimensions,
+                        depth_or_array_layers: 1,
+                    },
+                    TextureDimension::D2,
+                    data,
+                    TextureFormat::Rgba8Unorm,
+                )
+            })
+            .collect();
+    }
+
+    fn attribute(
+        mesh: &Mesh,
+        id: impl Into<bevy::render::mesh::MeshVertexAttributeId>,
+    ) -> Vec<Vec3> {
+        let Some(VertexAttributeValues::Float32x3(values)) = mesh.attribute(id) else {
+            panic!("Mesh attribute is missing");
+        };
+        return values
+            .iter()
+            .map(|value| Vec3::from_array(*value))
+            .collect();
+    }
+
+    fn single_row_image(data: Vec<u8>, width: u32, format: TextureFormat) -> Image {
+        return Image::new(
+            Extent3d {
+                width,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            format,
         );
-        face_normals.push(normal);
     }
 
-    for (i, &indice) in triangles.iter().enumerate() {
-        let vertex_index = indice as usize;
-        normals[vertex_index] += face_normals[i / 3];
+    fn test_lod_config() -> LodConfig {
+        return LodConfig {
+            chunk_resolution: 9,
+            ..default()
+        };
     }
 
-    for normal in &mut normals {
-        *normal = normal.normalize();
+    #[test]
+    fn shared_edges_match_across_faces() {
+        let lod_config = test_lod_config();
+        let resolution = lod_config.chunk_resolution;
+        let height_maps = test_height_maps(16);
+        let terrain_config = TerrainConfig::default();
+
+        let mut edge_vertices: Vec<(usize, Vec3, Vec3)> = Vec::new();
+        for face in 0..cube_map::FACE_DIRECTIONS.len() {
+            let mesh = spawn_chunk(
+                ChunkId::root(face),
+                &height_maps,
+                &lod_config,
+                &terrain_config,
+            );
+            let positions = attribute(&mesh, Mesh::ATTRIBUTE_POSITION);
+            let normals = attribute(&mesh, Mesh::ATTRIBUTE_NORMAL);
+            for y in 0..resolution {
+                for x in 0..resolution {
+                    if x != 0 && y != 0 && x != resolution - 1 && y != resolution - 1 {
+                        continue;
+                    }
+                    let index = (x + y * resolution) as usize;
+                    edge_vertices.push((face, positions[index], normals[index]));
+                }
+            }
+        }
+
+        for (face, position, normal) in edge_vertices.iter() {
+            let matches: Vec<&(usize, Vec3, Vec3)> = edge_vertices
+                .iter()
+                .filter(|(other_face, other_position, _)| {
+                    other_face != face && other_position.abs_diff_eq(*position, 1e-6)
+                })
+                .collect();
+            assert!(
+                !matches.is_empty(),
+                "Edge vertex {} of face {} has no partner on a neighbouring face",
+                position,
+                face
+            );
+            for (_, _, other_normal) in matches {
+                assert!(
+                    other_normal.abs_diff_eq(*normal, 1e-5),
+                    "Normals {} and {} disagree at {}",
+                    normal,
+                    other_normal,
+                    position
+                );
+            }
+        }
     }
 
-    return normals;
+    #[test]
+    fn chunks_of_different_depths_meet_without_cracks() {
+        let lod_config = test_lod_config();
+        let resolution = lod_config.chunk_resolution;
+        let last = resolution - 1;
+        let height_maps = test_height_maps(16);
+        let terrain_config = TerrainConfig::default();
+
+        // The lower left quarter of face 0, and the finer chunk touching the bottom half of its right edge
+        let coarse = ChunkId {
+            face: 0,
+            depth: 1,
+            x: 0,
+            y: 0,
+        };
+        let fine = ChunkId {
+            face: 0,
+            depth: 2,
+            x: 2,
+            y: 0,
+        };
+        let coarse_mesh = spawn_chunk(coarse, &height_maps, &lod_config, &terrain_config);
+        let fine_mesh = spawn_chunk(fine, &height_maps, &lod_config, &terrain_config);
+        let coarse_positions = attribute(&coarse_mesh, Mesh::ATTRIBUTE_POSITION);
+        let fine_positions = attribute(&fine_mesh, Mesh::ATTRIBUTE_POSITION);
+
+        let fine_left_edge: Vec<Vec3> = (0..resolution)
+            .map(|y| fine_positions[(y * resolution) as usize])
+            .collect();
+        for y in 0..=last / 2 {
+            let coarse_vertex = coarse_positions[(last + y * resolution) as usize];
+            assert!(
+                fine_left_edge.contains(&coarse_vertex),
+                "Coarse edge vertex {} is missing from the finer neighbour",
+                coarse_vertex
+            );
+        }
+
+        // Every border vertex gets a skirt vertex hanging below it
+        let grid_len = (resolution * resolution) as usize;
+        assert_eq!(coarse_positions.len(), grid_len + 4 * last as usize);
+        for (k, edge_index) in border_indices(resolution).iter().enumerate() {
+            let edge = coarse_positions[*edge_index as usize];
+            let skirt = coarse_positions[grid_len + k];
+            assert!(skirt.length() < edge.length());
+            assert!(skirt.normalize().abs_diff_eq(edge.normalize(), 1e-6));
+        }
+    }
+
+    #[test]
+    fn mesh_uvs_follow_the_province_map_layout() {
+        // Province, border and color textures are laid out by cube_map, so every vertex has to
+        // sit where cube_map puts its uv, with no per face flips in between
+        let height_maps = test_height_maps(8);
+        for face in 0..cube_map::FACE_DIRECTIONS.len() {
+            let mesh = spawn_chunk(
+                ChunkId::root(face),
+                &height_maps,
+                &test_lod_config(),
+                &TerrainConfig::default(),
+            );
+            let positions = attribute(&mesh, Mesh::ATTRIBUTE_POSITION);
+            let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0)
+            else {
+                panic!("chunk mesh without uvs");
+            };
+            for (position, uv) in positions.iter().zip(uvs) {
+                let uv = Vec2::from(*uv);
+                // Border vertices belong to two faces equally
+                if uv.min_element() == 0.0 || uv.max_element() == 1.0 {
+                    continue;
+                }
+                let (mapped_face, mapped_uv) = cube_map::direction_to_face_uv(*position);
+                assert_eq!(mapped_face, face);
+                assert!(
+                    mapped_uv.distance(uv) < 1e-5,
+                    "{} against {}",
+                    mapped_uv,
+                    uv
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn height_maps_keep_their_precision() {
+        let sixteen_bit = single_row_image(
+            [1000u16, 1001u16]
+                .iter()
+                .flat_map(|value| value.to_ne_bytes())
+                .collect(),
+            2,
+            TextureFormat::R16Uint,
+        );
+        let step = texel_height(&sixteen_bit, 1, 0) - texel_height(&sixteen_bit, 0, 0);
+        assert!((step - 1.0 / u16::MAX as f32).abs() < 1e-7);
+
+        let float = single_row_image(0.375f32.to_ne_bytes().to_vec(), 1, TextureFormat::R32Float);
+        for sampling in [
+            HeightSampling::Nearest,
+            HeightSampling::Bilinear,
+            HeightSampling::Bicubic,
+        ] {
+            assert_eq!(sample_height_map(Vec2::splat(0.3), &float, sampling), 0.375);
+        }
+    }
+
+    #[test]
+    fn filtered_sampling_blends_between_texels() {
+        let ramp = single_row_image(
+            vec![0, 0, 0, 255, 255, 255, 255, 255],
+            2,
+            TextureFormat::Rgba8Unorm,
+        );
+        let between = Vec2::new(0.5, 0.5);
+        assert_eq!(
+            sample_height_map(between, &ramp, HeightSampling::Bilinear),
+            0.5
+        );
+        assert_eq!(
+            sample_height_map(between, &ramp, HeightSampling::Bicubic),
+            0.5
+        );
+        let quarter = Vec2::new(0.375, 0.5);
+        assert!((sample_height_map(quarter, &ramp, HeightSampling::Bilinear) - 0.25).abs() < 1e-6);
+        // Texel centres are hit exactly by every filter
+        let centre = Vec2::new(0.75, 0.5);
+        assert_eq!(
+            sample_height_map(centre, &ramp, HeightSampling::Nearest),
+            1.0
+        );
+        assert_eq!(
+            sample_height_map(centre, &ramp, HeightSampling::Bicubic),
+            1.0
+        );
+    }
 }
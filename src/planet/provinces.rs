@@ -1,30 +1,47 @@
-use image::{imageops, Rgb, RgbImage, Rgba, RgbaImage};
-use rand::prelude::*;
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
 
-use super::noise;
+use bevy::{
+    math::Vec3,
+    render::texture::Image,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use image::{Rgb, RgbImage, Rgba, RgbaImage};
+use rand::{prelude::*, rngs::StdRng};
 
-const DISPLACEMENT_FACTOR: f64 = 84.0;
+use super::{
+    cube_map::{self, FACE_DIRECTIONS},
+    kd_tree::KdTree,
+    noise::{self, NoiseSource},
+    planet_mesh::HeightField,
+    ProvinceGeometry, ProvinceGraph, ProvinceId, ProvinceMap, MARS_RADIUS_KM,
+};
+use crate::config_parser::{BoundaryMode, NoiseConfig, ProvincesConfig, TerrainConfig};
 
-pub fn create_province_colors(cell_count: usize, dimensions: u32) -> Vec<(Rgb<u8>, u32, u32, u32)> {
+pub fn create_province_colors(
+    cell_count: usize,
+    dimensions: u32,
+    rng: &mut StdRng,
+) -> Vec<(Rgb<u8>, u32, u32, u32)> {
     let mut used_colors: Vec<(Rgb<u8>, u32, u32, u32)> = Vec::new();
+    // Looked up on every draw, so large province counts do not go quadratic
+    let mut used_coords: HashSet<(u32, u32, u32)> = HashSet::new();
+    let mut used_rgb: HashSet<Rgb<u8>> = HashSet::new();
     for _ in 0..cell_count {
         loop {
-            let new_x = rand::thread_rng().gen_range(1..=(dimensions - 1));
-            let new_y = rand::thread_rng().gen_range(1..=(dimensions - 1));
-            let new_z = rand::thread_rng().gen_range(1..=(dimensions - 1));
-            let contains_coords = used_colors
-                .iter()
-                .any(|&(_, ref x, ref y, ref z)| *x == new_x && *y == new_y && *z == new_z);
-            if !contains_coords {
+            let new_x = rng.gen_range(1..=(dimensions - 1));
+            let new_y = rng.gen_range(1..=(dimensions - 1));
+            let new_z = rng.gen_range(1..=(dimensions - 1));
+            if used_coords.insert((new_x, new_y, new_z)) {
                 loop {
-                    let r: u8 = rand::thread_rng().gen_range(1..=255);
-                    let g: u8 = rand::thread_rng().gen_range(1..=255);
-                    let b: u8 = rand::thread_rng().gen_range(1..=255);
+                    let r: u8 = rng.gen_range(1..=255);
+                    let g: u8 = rng.gen_range(1..=255);
+                    let b: u8 = rng.gen_range(1..=255);
                     let new_color = image::Rgb([r, g, b]);
-                    let contains_new_color = used_colors
-                        .iter()
-                        .any(|&(ref color, _, _, _)| *color == new_color);
-                    if !contains_new_color {
+                    if used_rgb.insert(new_color) {
                         used_colors.push((new_color, new_x, new_y, new_z));
                         break;
                     }
@@ -38,68 +55,294 @@ pub fn create_province_colors(cell_count: usize, dimensions: u32) -> Vec<(Rgb<u8
     return used_colors;
 }
 
-pub fn create_provinces_images(
+/// How much the province noise stretches or shrinks steps over flat ground in terrain mode.
+const TERRAIN_NOISE_ROUGHNESS: f64 = 0.5;
+
+/// Every pixel step a terrain weighted province can take, including the diagonals so the
+/// borders are not pulled onto the pixel axes.
+const PIXEL_STEPS: [(i32, i32); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// Assigns every pixel of the six faces to a province, with one task per face on the async
+/// compute pool. The face tasks are awaited rather than scoped, so no pool thread is blocked
+/// while they run.
+pub async fn create_provinces_images(
     colors: Vec<(Rgb<u8>, u32, u32, u32)>,
     dimensions: u32,
+    seed: u64,
+    provinces_config: &ProvincesConfig,
+    noise_config: &NoiseConfig,
+    height_maps: Arc<Vec<Image>>,
+    terrain_config: &TerrainConfig,
 ) -> Vec<RgbImage> {
-    let noise_map: Vec<Vec<Vec<f64>>> = noise::make_perlin_noise(dimensions);
-    let mut voronoi_faces: Vec<RgbImage> = Vec::with_capacity(6);
-    for face_index in 0..6 {
-        let mut image: RgbImage = RgbImage::new(dimensions, dimensions);
-        let mut previous_noise: f64 = 0.0;
-        for x in 0..dimensions {
-            for y in 0..dimensions {
-                let (nx, ny, nz) = match face_index {
-                    0 => ((dimensions - 1) as f64, x as f64, y as f64), // Positive X
-                    1 => (0.0, x as f64, y as f64),                     // Negative X
-                    2 => (x as f64, (dimensions - 1) as f64, y as f64), // Positive Y
-                    3 => (x as f64, 0.0, y as f64),                     // Negative Y
-                    4 => (x as f64, y as f64, (dimensions - 1) as f64), // Positive Z
-                    _ => (x as f64, y as f64, 0.0),                     // Negative Z
-                };
-                let mut noise_value = noise_map[nx as usize][ny as usize][nz as usize];
-                if noise_value.is_nan() {
-                    noise_value = previous_noise;
-                }
-                previous_noise = noise_value;
-                let distorted_x = nx + noise_value * DISPLACEMENT_FACTOR;
-                let distorted_y = ny + noise_value * DISPLACEMENT_FACTOR;
-                let distorted_z = nz + noise_value * DISPLACEMENT_FACTOR;
-                let mut closest = None;
-                let mut min_distance = f64::MAX;
-                for (color, px, py, pz) in &colors {
-                    let distance = ((distorted_x - *px as f64).powi(2)
-                        + (distorted_y - *py as f64).powi(2)
-                        + (distorted_z - *pz as f64).powi(2))
-                    .sqrt();
-                    if distance < min_distance {
-                        min_distance = distance;
-                        closest = Some(*color);
-                    }
-                }
-                if let Some(color) = closest {
-                    *image.get_pixel_mut(x, y) = color;
+    let thread_pool = AsyncComputeTaskPool::get();
+    let noise = Arc::new(noise::from_config(seed, noise_config));
+    return match provinces_config.boundaries {
+        BoundaryMode::Noise => {
+            let displacement_factor = provinces_config.displacement_factor;
+            let seed_points = Arc::new(KdTree::new(
+                colors
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (_, x, y, z))| ([*x as f64, *y as f64, *z as f64], index))
+                    .collect(),
+            ));
+            let colors = Arc::new(colors);
+            let tasks = (0..FACE_DIRECTIONS.len())
+                .map(|face| {
+                    let (colors, noise, seed_points) =
+                        (colors.clone(), noise.clone(), seed_points.clone());
+                    return thread_pool.spawn(async move {
+                        return noise_distorted_face(
+                            face,
+                            &colors,
+                            dimensions,
+                            &noise,
+                            &seed_points,
+                            displacement_factor,
+                        );
+                    });
+                })
+                .collect();
+            join_faces(tasks).await
+        }
+        BoundaryMode::Terrain => {
+            let tasks = (0..FACE_DIRECTIONS.len())
+                .map(|face| {
+                    let (noise, height_maps, terrain_config) =
+                        (noise.clone(), height_maps.clone(), terrain_config.clone());
+                    return thread_pool.spawn(async move {
+                        let height_field = HeightField::new(&height_maps, &terrain_config);
+                        return terrain_samples(face, dimensions, &noise, &height_field);
+                    });
+                })
+                .collect();
+            let samples: Vec<(Vec3, f64, f64)> =
+                join_faces(tasks).await.into_iter().flatten().collect();
+            terrain_weighted_provinces(
+                &colors,
+                dimensions,
+                &samples,
+                terrain_config.height_map_scale as f64 * provinces_config.slope_cost,
+            )
+        }
+    };
+}
+
+/// Waits for the per face tasks, keeping the faces in `FACE_DIRECTIONS` order.
+async fn join_faces<T>(tasks: Vec<Task<T>>) -> Vec<T> {
+    let mut faces = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        faces.push(task.await);
+    }
+    return faces;
+}
+
+/// Assigns every pixel of a face to the nearest seed after displacing the pixel by the noise.
+fn noise_distorted_face(
+    face: usize,
+    colors: &[(Rgb<u8>, u32, u32, u32)],
+    dimensions: u32,
+    noise: &noise::ConfiguredNoise,
+    seed_points: &KdTree,
+    displacement_factor: f64,
+) -> RgbImage {
+    return RgbImage::from_fn(dimensions, dimensions, |x, y| {
+        let grid_point = noise::map_cell_point(face, x, y, dimensions);
+        let (nx, ny, nz) = (
+            grid_point.x as f64,
+            grid_point.y as f64,
+            grid_point.z as f64,
+        );
+        let noise_value = noise.sample(grid_point) as f64;
+        let distorted = [
+            nx + noise_value * displacement_factor,
+            ny + noise_value * displacement_factor,
+            nz + noise_value * displacement_factor,
+        ];
+        match seed_points.nearest(distorted) {
+            Some(index) => colors[index].0,
+            None => Rgb([0, 0, 0]),
+        }
+    });
+}
+
+/// Direction, height and noise stretch of every pixel of a face, row by row. Sampled once up
+/// front since every pixel is stepped onto from up to eight neighbours.
+fn terrain_samples(
+    face: usize,
+    dimensions: u32,
+    noise: &noise::ConfiguredNoise,
+    height_field: &HeightField,
+) -> Vec<(Vec3, f64, f64)> {
+    return (0..dimensions * dimensions)
+        .map(|i| {
+            let (x, y) = (i % dimensions, i / dimensions);
+            let direction = cube_map::pixel_point(face, x, y, dimensions).normalize();
+            let height = height_field.height(direction) as f64;
+            let grid_point = noise::map_cell_point(face, x, y, dimensions);
+            let stretch = 1.0 + TERRAIN_NOISE_ROUGHNESS * noise.sample(grid_point) as f64;
+            return (direction, height, stretch);
+        })
+        .collect();
+}
+
+/// A pixel waiting to be claimed, ordered so the cheapest comes out of the heap first.
+#[derive(PartialEq)]
+struct Frontier {
+    cost: f64,
+    pixel: usize,
+    province: usize,
+}
+
+impl Eq for Frontier {}
+
+impl Ord for Frontier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Ties fall back to the pixel index so the result does not depend on heap internals
+        return other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| other.pixel.cmp(&self.pixel));
+    }
+}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+/// Grows all provinces from their seeds at once over the pixels of the cube faces, and
+/// gives every pixel to the province that reaches it cheapest. A step costs its arc length,
+/// stretched by the noise, plus `climb_cost` times the height it climbs or descends.
+fn terrain_weighted_provinces(
+    colors: &[(Rgb<u8>, u32, u32, u32)],
+    dimensions: u32,
+    samples: &[(Vec3, f64, f64)],
+    climb_cost: f64,
+) -> Vec<RgbImage> {
+    let face_pixels = (dimensions * dimensions) as usize;
+    let pixel_index = |face: usize, x: u32, y: u32| -> usize {
+        return face * face_pixels + (y * dimensions + x) as usize;
+    };
+
+    let mut best_costs = vec![f64::INFINITY; samples.len()];
+    let mut owners: Vec<Option<usize>> = vec![None; samples.len()];
+    let mut frontier = BinaryHeap::new();
+    let center = (dimensions - 1) as f32 / 2.0;
+    for (province, (_, x, y, z)) in colors.iter().enumerate() {
+        let seed_direction = Vec3::new(*x as f32, *y as f32, *z as f32) - center;
+        let (face, x, y) = cube_map::direction_to_pixel(seed_direction, dimensions);
+        // Seeds landing on a pixel an earlier seed took move over to the closest free pixel,
+        // so no province is left without any pixels
+        let Some(pixel) = nearest_free_pixel(face, x, y, dimensions, &best_costs) else {
+            continue;
+        };
+        best_costs[pixel] = 0.0;
+        frontier.push(Frontier {
+            cost: 0.0,
+            pixel,
+            province,
+        });
+    }
+
+    while let Some(Frontier {
+        cost,
+        pixel,
+        province,
+    }) = frontier.pop()
+    {
+        if owners[pixel].is_some() {
+            continue;
+        }
+        owners[pixel] = Some(province);
+        let face = pixel / face_pixels;
+        let x = (pixel % face_pixels) as u32 % dimensions;
+        let y = (pixel % face_pixels) as u32 / dimensions;
+        let (direction, height, stretch) = samples[pixel];
+        for (dx, dy) in PIXEL_STEPS {
+            let (next_face, next_x, next_y) =
+                cube_map::neighbor_pixel(face, x, y, dx, dy, dimensions);
+            let next = pixel_index(next_face, next_x, next_y);
+            if owners[next].is_some() {
+                continue;
+            }
+            let (next_direction, next_height, next_stretch) = samples[next];
+            let arc = direction.angle_between(next_direction) as f64;
+            let next_cost = cost
+                + arc * (stretch + next_stretch) * 0.5
+                + climb_cost * (next_height - height).abs();
+            if next_cost < best_costs[next] {
+                best_costs[next] = next_cost;
+                frontier.push(Frontier {
+                    cost: next_cost,
+                    pixel: next,
+                    province,
+                });
+            }
+        }
+    }
+
+    return (0..FACE_DIRECTIONS.len())
+        .map(|face| {
+            RgbImage::from_fn(dimensions, dimensions, |x, y| {
+                match owners[pixel_index(face, x, y)] {
+                    Some(province) => colors[province].0,
+                    None => Rgb([0, 0, 0]),
                 }
+            })
+        })
+        .collect();
+}
+
+/// Walks outwards from a seed pixel, across face seams, to the closest pixel no seed has
+/// claimed yet. Only fails when there are more seeds than pixels.
+fn nearest_free_pixel(
+    face: usize,
+    x: u32,
+    y: u32,
+    dimensions: u32,
+    best_costs: &[f64],
+) -> Option<usize> {
+    let face_pixels = (dimensions * dimensions) as usize;
+    let pixel_index = |face: usize, x: u32, y: u32| -> usize {
+        return face * face_pixels + (y * dimensions + x) as usize;
+    };
+    let mut visited = HashSet::from([pixel_index(face, x, y)]);
+    let mut queue = VecDeque::from([(face, x, y)]);
+    while let Some((face, x, y)) = queue.pop_front() {
+        let pixel = pixel_index(face, x, y);
+        if best_costs[pixel] > 0.0 {
+            return Some(pixel);
+        }
+        for (dx, dy) in PIXEL_STEPS {
+            let next = cube_map::neighbor_pixel(face, x, y, dx, dy, dimensions);
+            if visited.insert(pixel_index(next.0, next.1, next.2)) {
+                queue.push_back(next);
             }
         }
-        voronoi_faces.push(image);
     }
-    voronoi_faces[0] = imageops::flip_horizontal(&voronoi_faces[0]);
-    voronoi_faces[2] = imageops::rotate270(&voronoi_faces[2]);
-    voronoi_faces[3] = imageops::flip_horizontal(&voronoi_faces[3]);
-    voronoi_faces[3] = imageops::rotate90(&voronoi_faces[3]);
-    voronoi_faces[4] = imageops::flip_horizontal(&voronoi_faces[4]);
-    return voronoi_faces;
+    return None;
 }
 
-pub fn get_colors(images: &Vec<RgbImage>) -> Vec<Rgb<u8>> {
+pub fn get_colors(images: &[RgbImage]) -> Vec<Rgb<u8>> {
     let mut colors: Vec<Rgb<u8>> = Vec::new();
+    let mut seen_colors: HashSet<Rgb<u8>> = HashSet::new();
     for image in images {
         for x in 0..image.width() {
             for y in 0..image.height() {
                 let pixel = image.get_pixel(x, y);
-                if !colors.contains(pixel) {
-                    colors.push(pixel.clone());
+                if seen_colors.insert(*pixel) {
+                    colors.push(*pixel);
                 }
             }
         }
@@ -107,7 +350,7 @@ pub fn get_colors(images: &Vec<RgbImage>) -> Vec<Rgb<u8>> {
     return colors;
 }
 
-pub fn get_border_images(dimensions: u32, images: &Vec<RgbImage>) -> Vec<RgbaImage> {
+pub fn get_border_images(dimensions: u32, images: &[RgbImage]) -> Vec<RgbaImage> {
     let mut border_images: Vec<RgbaImage> = Vec::with_capacity(images.len());
     for image in images {
         let mut border_image: RgbaImage = RgbaImage::new(dimensions, dimensions);
@@ -134,3 +377,271 @@ pub fn get_border_images(dimensions: u32, images: &Vec<RgbImage>) -> Vec<RgbaIma
     }
     return border_images;
 }
+
+/// Converts the colored province images into a per-pixel province id lookup.
+pub fn get_province_map(images: &[RgbImage], colors: &[Rgb<u8>]) -> ProvinceMap {
+    let ids: HashMap<Rgb<u8>, ProvinceId> = colors
+        .iter()
+        .enumerate()
+        .map(|(id, color)| (*color, id as ProvinceId))
+        .collect();
+    let dimensions = images.first().map_or(0, |image| image.width());
+    let faces = images
+        .iter()
+        .map(|image| image.pixels().map(|pixel| ids[pixel]).collect())
+        .collect();
+    return ProvinceMap { dimensions, faces };
+}
+
+/// Paints a province id map back into colored province images.
+pub fn get_province_images(province_map: &ProvinceMap, colors: &[Rgb<u8>]) -> Vec<RgbImage> {
+    let dimensions = province_map.dimensions;
+    return province_map
+        .faces
+        .iter()
+        .map(|face| {
+            RgbImage::from_fn(dimensions, dimensions, |x, y| {
+                colors[face[(y * dimensions + x) as usize] as usize]
+            })
+        })
+        .collect();
+}
+
+/// Walks every pair of touching pixels, including pairs that straddle a cube face seam, and
+/// records which provinces touch and how many pixel edges they share.
+pub fn get_province_graph(images: &[RgbImage], colors: &[Rgb<u8>]) -> ProvinceGraph {
+    let ids: HashMap<Rgb<u8>, ProvinceId> = colors
+        .iter()
+        .enumerate()
+        .map(|(id, color)| (*color, id as ProvinceId))
+        .collect();
+    let mut graph = ProvinceGraph::default();
+    for (face, image) in images.iter().enumerate() {
+        let dimensions = image.width();
+        for x in 0..dimensions {
+            for y in 0..dimensions {
+                let current_color = image.get_pixel(x, y);
+                for (dx, dy) in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
+                    let (neighbor_face, nx, ny) =
+                        cube_map::neighbor_pixel(face, x, y, dx, dy, dimensions);
+                    // Every shared edge is seen from both sides, so only count it once
+                    let counted_side = if neighbor_face == face {
+                        dx + dy > 0
+                    } else {
+                        face < neighbor_face
+                    };
+                    if !counted_side {
+                        continue;
+                    }
+                    let neighbor_color = images[neighbor_face].get_pixel(nx, ny);
+                    if current_color != neighbor_color {
+                        graph.add_border(ids[current_color], ids[neighbor_color]);
+                    }
+                }
+            }
+        }
+    }
+    return graph;
+}
+
+/// Integrates every pixel of the province maps onto the unit sphere to find each province's
+/// centroid, surface area, lat/long bounds and the faces it covers. Indexed by province id.
+pub fn get_province_geometry(images: &[RgbImage], colors: &[Rgb<u8>]) -> Vec<ProvinceGeometry> {
+    let ids: HashMap<Rgb<u8>, usize> = colors
+        .iter()
+        .enumerate()
+        .map(|(id, color)| (*color, id))
+        .collect();
+    let mut centroid_sums: Vec<Vec3> = vec![Vec3::ZERO; colors.len()];
+    let mut solid_angles: Vec<f32> = vec![0.0; colors.len()];
+    let mut pixel_counts: Vec<u32> = vec![0; colors.len()];
+    let mut latitudes: Vec<(f32, f32)> = vec![(f32::MAX, f32::MIN); colors.len()];
+    // Longitudes are tracked both in [-180, 180) and [0, 360) to catch provinces over the wrap
+    let mut longitudes: Vec<(f32, f32)> = vec![(f32::MAX, f32::MIN); colors.len()];
+    let mut shifted_longitudes: Vec<(f32, f32)> = vec![(f32::MAX, f32::MIN); colors.len()];
+    let mut faces: Vec<Vec<Vec3>> = vec![Vec::new(); colors.len()];
+
+    for (face, image) in images.iter().enumerate() {
+        let dimensions = image.width();
+        let pixel_size = 2.0 / dimensions as f32;
+        for x in 0..dimensions {
+            for y in 0..dimensions {
+                let id = ids[image.get_pixel(x, y)];
+                let cube_point = cube_map::pixel_point(face, x, y, dimensions);
+                let direction = cube_point.normalize();
+
+                // Solid angle of a cube face pixel projected onto the unit sphere
+                let solid_angle = pixel_size * pixel_size / cube_point.length().powi(3);
+                centroid_sums[id] += direction * solid_angle;
+                solid_angles[id] += solid_angle;
+                pixel_counts[id] += 1;
+
+                let (latitude, longitude) = cube_map::direction_to_lat_long(direction);
+                let shifted_longitude = longitude.rem_euclid(360.0);
+                latitudes[id].0 = latitudes[id].0.min(latitude);
+                latitudes[id].1 = latitudes[id].1.max(latitude);
+                longitudes[id].0 = longitudes[id].0.min(longitude);
+                longitudes[id].1 = longitudes[id].1.max(longitude);
+                shifted_longitudes[id].0 = shifted_longitudes[id].0.min(shifted_longitude);
+                shifted_longitudes[id].1 = shifted_longitudes[id].1.max(shifted_longitude);
+
+                let face_direction = FACE_DIRECTIONS[face];
+                if !faces[id].contains(&face_direction) {
+                    faces[id].push(face_direction);
+                }
+            }
+        }
+    }
+
+    let mut geometry: Vec<ProvinceGeometry> = Vec::with_capacity(colors.len());
+    for id in 0..colors.len() {
+        let (mut min_longitude, mut max_longitude) = longitudes[id];
+        let (shifted_min, shifted_max) = shifted_longitudes[id];
+        if shifted_max - shifted_min < max_longitude - min_longitude {
+            min_longitude = wrap_longitude(shifted_min);
+            max_longitude = wrap_longitude(shifted_max);
+        }
+        geometry.push(ProvinceGeometry {
+            centroid: centroid_sums[id].normalize_or_zero(),
+            area_pixels: pixel_counts[id],
+            area_km2: solid_angles[id] * MARS_RADIUS_KM * MARS_RADIUS_KM,
+            min_latitude: latitudes[id].0,
+            max_latitude: latitudes[id].1,
+            min_longitude,
+            max_longitude,
+            faces: std::mem::take(&mut faces[id]),
+        });
+    }
+    return geometry;
+}
+
+fn wrap_longitude(longitude: f32) -> f32 {
+    if longitude >= 180.0 {
+        return longitude - 360.0;
+    }
+    return longitude;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planet::terrain::height_faces;
+    use bevy::{tasks::TaskPool, utils::default};
+
+    fn terrain_provinces(
+        colors: &[(Rgb<u8>, u32, u32, u32)],
+        dimensions: u32,
+        boundaries: BoundaryMode,
+        height_maps: Vec<Image>,
+    ) -> Vec<RgbImage> {
+        AsyncComputeTaskPool::get_or_init(TaskPool::new);
+        return futures_lite::future::block_on(create_provinces_images(
+            colors.to_vec(),
+            dimensions,
+            1,
+            &ProvincesConfig {
+                boundaries,
+                ..default()
+            },
+            &NoiseConfig {
+                strength: 0.0,
+                ..default()
+            },
+            Arc::new(height_maps),
+            &TerrainConfig::default(),
+        ));
+    }
+
+    #[test]
+    fn terrain_borders_settle_on_ridges() {
+        let dimensions = 33;
+        // A ring shaped ridge around +X, well off the halfway line between the two seeds
+        let height_maps = height_faces(dimensions, |direction| {
+            if (direction.x - 0.3).abs() < 0.05 {
+                1.0
+            } else {
+                0.0
+            }
+        });
+        let colors = [
+            (Rgb([1, 0, 0]), dimensions - 1, 16, 16),
+            (Rgb([2, 0, 0]), 0, 16, 16),
+        ];
+        let province_owning = |boundaries: BoundaryMode, direction: Vec3| {
+            let images = terrain_provinces(&colors, dimensions, boundaries, height_maps.clone());
+            let (face, x, y) = cube_map::direction_to_pixel(direction, dimensions);
+            return images[face].get_pixel(x, y)[0];
+        };
+
+        // Between the halfway line and the ridge, closer to the +X seed
+        let inside = Vec3::new(0.15, 0.0, 1.0).normalize();
+        assert_eq!(province_owning(BoundaryMode::Noise, inside), 1);
+        assert_eq!(province_owning(BoundaryMode::Terrain, inside), 2);
+        let within_ridge = Vec3::new(0.7, 0.0, 0.7).normalize();
+        assert_eq!(province_owning(BoundaryMode::Terrain, within_ridge), 1);
+    }
+
+    #[test]
+    fn seeds_on_the_same_pixel_both_get_a_province() {
+        let dimensions = 33;
+        // Both seeds project onto the center pixel of the +X face
+        let colors = [
+            (Rgb([1, 0, 0]), dimensions - 1, 16, 16),
+            (Rgb([2, 0, 0]), dimensions - 2, 16, 16),
+            (Rgb([3, 0, 0]), 0, 16, 16),
+        ];
+        let images = terrain_provinces(
+            &colors,
+            dimensions,
+            BoundaryMode::Terrain,
+            height_faces(dimensions, |_| 0.0),
+        );
+        let mut found = get_colors(&images);
+        found.sort_by_key(|color| color[0]);
+        assert_eq!(found, vec![Rgb([1, 0, 0]), Rgb([2, 0, 0]), Rgb([3, 0, 0])]);
+    }
+
+    #[test]
+    fn one_province_per_face_covers_a_sixth_of_the_planet() {
+        let dimensions = 32;
+        let colors: Vec<Rgb<u8>> = (0..FACE_DIRECTIONS.len())
+            .map(|face| Rgb([face as u8 + 1, 0, 0]))
+            .collect();
+        let images: Vec<RgbImage> = colors
+            .iter()
+            .map(|color| RgbImage::from_pixel(dimensions, dimensions, *color))
+            .collect();
+        let geometry = get_province_geometry(&images, &colors);
+
+        let face_area = 4.0 * std::f32::consts::PI * MARS_RADIUS_KM * MARS_RADIUS_KM / 6.0;
+        for (face, province) in geometry.iter().enumerate() {
+            let direction = FACE_DIRECTIONS[face];
+            assert_eq!(province.area_pixels, dimensions * dimensions);
+            assert!(
+                (province.area_km2 / face_area - 1.0).abs() < 0.01,
+                "face {} covers {} km²",
+                face,
+                province.area_km2
+            );
+            assert!(
+                province.centroid.distance(direction) < 1e-4,
+                "face {}",
+                face
+            );
+            assert_eq!(province.faces, vec![direction]);
+        }
+
+        // -Z faces longitude 0 and +Z faces the wrap at 180°
+        let front = &geometry[5];
+        assert!(front.min_longitude < front.max_longitude);
+        assert!((front.min_longitude + 45.0).abs() < 1.0);
+        assert!((front.max_longitude - 45.0).abs() < 1.0);
+        let back = &geometry[4];
+        assert!(back.min_longitude > back.max_longitude);
+        assert!((back.min_longitude - 135.0).abs() < 1.0);
+        assert!((back.max_longitude + 135.0).abs() < 1.0);
+        assert!(back.max_latitude < 55.0 && back.min_latitude > -55.0);
+        // The polar faces reach up to the pole
+        assert!(geometry[2].max_latitude > 85.0 && geometry[2].min_latitude > 35.0);
+    }
+}
@@ -0,0 +1,412 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use bevy::{
+    pbr::ExtendedMaterial,
+    prelude::*,
+    render::primitives::Sphere,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use futures_lite::future;
+
+use super::{cube_map, planet_mesh, PlanetMaterial};
+use crate::{
+    camera_system,
+    config_parser::{LodConfig, TerrainConfig},
+};
+
+type PlanetMaterialHandle = Handle<ExtendedMaterial<StandardMaterial, PlanetMaterial>>;
+
+/// A square piece of a cube face, at `depth` the face is split into `2^depth` by `2^depth`
+/// chunks and `x`/`y` pick one of them along the face axes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkId {
+    pub face: usize,
+    pub depth: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl ChunkId {
+    pub fn root(face: usize) -> Self {
+        return ChunkId {
+            face,
+            depth: 0,
+            x: 0,
+            y: 0,
+        };
+    }
+
+    pub fn children(&self) -> [ChunkId; 4] {
+        let child = |dx: u32, dy: u32| ChunkId {
+            face: self.face,
+            depth: self.depth + 1,
+            x: self.x * 2 + dx,
+            y: self.y * 2 + dy,
+        };
+        return [child(0, 0), child(1, 0), child(0, 1), child(1, 1)];
+    }
+
+    pub fn parent(&self) -> Option<ChunkId> {
+        if self.depth == 0 {
+            return None;
+        }
+        return Some(ChunkId {
+            face: self.face,
+            depth: self.depth - 1,
+            x: self.x / 2,
+            y: self.y / 2,
+        });
+    }
+
+    /// Width of the chunk on the unit cube, where a whole face is 2 wide.
+    pub fn edge_length(&self) -> f32 {
+        return 2.0 / (1u64 << self.depth) as f32;
+    }
+}
+
+/// A generated chunk mesh together with the sphere bounding all of its vertices.
+struct ChunkMesh {
+    handle: Handle<Mesh>,
+    bounds: Sphere,
+}
+
+/// The chunk quadtrees of all six faces. Leaves are the chunks currently drawn, a leaf only
+/// gets replaced by its children once all four meshes have been generated on the task pool,
+/// so the surface never has holes while the tree refines.
+#[derive(Resource)]
+pub struct PlanetQuadtree {
+    height_maps: Arc<Vec<Image>>,
+    lod_config: LodConfig,
+    terrain_config: TerrainConfig,
+    /// Face entities and the material their chunks share, in `FACE_DIRECTIONS` order.
+    faces: Vec<Option<(Entity, PlanetMaterialHandle)>>,
+    leaves: HashMap<ChunkId, Entity>,
+    /// Meshes of the leaves, their ancestors for merging back, and children being prepared.
+    meshes: HashMap<ChunkId, ChunkMesh>,
+    pending: HashMap<ChunkId, Task<Mesh>>,
+}
+
+impl PlanetQuadtree {
+    pub fn new(
+        height_maps: Arc<Vec<Image>>,
+        lod_config: LodConfig,
+        terrain_config: TerrainConfig,
+    ) -> Self {
+        return PlanetQuadtree {
+            height_maps,
+            lod_config,
+            terrain_config,
+            faces: vec![None; cube_map::FACE_DIRECTIONS.len()],
+            leaves: HashMap::new(),
+            meshes: HashMap::new(),
+            pending: HashMap::new(),
+        };
+    }
+
+    pub fn insert_mesh(&mut self, chunk: ChunkId, mesh: Mesh, meshes: &mut Assets<Mesh>) {
+        let bounds = chunk_bounds(&mesh);
+        self.meshes.insert(
+            chunk,
+            ChunkMesh {
+                handle: meshes.add(mesh),
+                bounds,
+            },
+        );
+    }
+
+    /// Registers the entity of a face and starts it off with its root chunk.
+    pub fn attach_face(
+        &mut self,
+        commands: &mut Commands,
+        face: usize,
+        entity: Entity,
+        material: PlanetMaterialHandle,
+    ) {
+        if let Some(slot) = self.faces.get_mut(face) {
+            *slot = Some((entity, material));
+            self.spawn_leaf(commands, ChunkId::root(face));
+        }
+    }
+
+    /// Splits leaves the camera is close to and merges the ones it moved away from. Leaves
+    /// split inside `split_distance` of their bounding sphere but only merge back once the
+    /// camera is `merge_hysteresis` further out than that. `camera_position` is in the
+    /// planet's local space.
+    pub fn update(&mut self, commands: &mut Commands, camera_position: Vec3) {
+        let leaves: Vec<ChunkId> = self.leaves.keys().copied().collect();
+        let mut wanted: HashSet<ChunkId> = HashSet::new();
+        let mut merged: HashSet<ChunkId> = HashSet::new();
+
+        for chunk in leaves {
+            if !self.leaves.contains_key(&chunk) {
+                continue;
+            }
+            if self.within_split_range(chunk, camera_position, 1.0) {
+                let children = chunk.children();
+                for child in children {
+                    wanted.insert(child);
+                    self.request_mesh(child);
+                }
+                if children.iter().all(|child| self.meshes.contains_key(child)) {
+                    self.despawn_leaf(commands, chunk);
+                    for child in children {
+                        self.spawn_leaf(commands, child);
+                    }
+                }
+                continue;
+            }
+
+            let Some(parent) = chunk.parent() else {
+                continue;
+            };
+            let merge_scale = 1.0 + self.lod_config.merge_hysteresis;
+            if merged.contains(&parent)
+                || self.within_split_range(parent, camera_position, merge_scale)
+            {
+                continue;
+            }
+            let siblings = parent.children();
+            if siblings
+                .iter()
+                .all(|sibling| self.leaves.contains_key(sibling))
+            {
+                for sibling in siblings {
+                    self.despawn_leaf(commands, sibling);
+                }
+                self.spawn_leaf(commands, parent);
+                merged.insert(parent);
+            }
+        }
+
+        // Drop work and meshes nothing is going to show anymore. Ancestors of the leaves are
+        // walked on their own, since a leaf that is also wanted must still keep its parent
+        // around to merge back into
+        self.pending.retain(|chunk, _| wanted.contains(chunk));
+        let mut needed: HashSet<ChunkId> = HashSet::new();
+        for leaf in self.leaves.keys() {
+            let mut chunk = Some(*leaf);
+            while let Some(current) = chunk {
+                if !needed.insert(current) {
+                    break;
+                }
+                chunk = current.parent();
+            }
+        }
+        needed.extend(wanted);
+        self.meshes.retain(|chunk, _| needed.contains(chunk));
+    }
+
+    /// Collects chunk meshes that finished generating.
+    pub fn poll_tasks(&mut self, meshes: &mut Assets<Mesh>) {
+        let mut finished: Vec<(ChunkId, Mesh)> = Vec::new();
+        for (chunk, task) in self.pending.iter_mut() {
+            if let Some(mesh) = future::block_on(future::poll_once(task)) {
+                finished.push((*chunk, mesh));
+            }
+        }
+        for (chunk, mesh) in finished {
+            self.pending.remove(&chunk);
+            self.insert_mesh(chunk, mesh, meshes);
+        }
+    }
+
+    /// Whether the camera is within `split_distance * scale` chunk widths of the chunk's
+    /// bounding sphere. Chunks without a mesh yet, or at the deepest level, never are.
+    fn within_split_range(&self, chunk: ChunkId, camera_position: Vec3, scale: f32) -> bool {
+        if chunk.depth >= self.lod_config.max_depth {
+            return false;
+        }
+        let Some(chunk_mesh) = self.meshes.get(&chunk) else {
+            return false;
+        };
+        return distance_to_sphere(&chunk_mesh.bounds, camera_position)
+            < self.lod_config.split_distance * scale * chunk.edge_length();
+    }
+
+    fn request_mesh(&mut self, chunk: ChunkId) {
+        if self.meshes.contains_key(&chunk) || self.pending.contains_key(&chunk) {
+            return;
+        }
+        let height_maps = self.height_maps.clone();
+        let lod_config = self.lod_config.clone();
+        let terrain_config = self.terrain_config.clone();
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            return planet_mesh::spawn_chunk(chunk, &height_maps, &lod_config, &terrain_config);
+        });
+        self.pending.insert(chunk, task);
+    }
+
+    fn spawn_leaf(&mut self, commands: &mut Commands, chunk: ChunkId) {
+        let (Some(Some((face_entity, material))), Some(chunk_mesh)) =
+            (self.faces.get(chunk.face), self.meshes.get(&chunk))
+        else {
+            return;
+        };
+        let entity = commands
+            .spawn((
+                MaterialMeshBundle {
+                    mesh: chunk_mesh.handle.clone(),
+                    material: material.clone(),
+                    ..default()
+                },
+                camera_system::ThirdPersonCameraTarget,
+            ))
+            .set_parent(*face_entity)
+            .id();
+        self.leaves.insert(chunk, entity);
+    }
+
+    fn despawn_leaf(&mut self, commands: &mut Commands, chunk: ChunkId) {
+        if let Some(entity) = self.leaves.remove(&chunk) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Smallest sphere around the axis aligned box of the mesh positions, which is close
+/// enough to minimal for the gently curved chunks of a face.
+fn chunk_bounds(mesh: &Mesh) -> Sphere {
+    let Some(positions) = mesh
+        .attribute(Mesh::ATTRIBUTE_POSITION)
+        .and_then(|positions| positions.as_float3())
+    else {
+        return Sphere::default();
+    };
+    let (min, max) = positions.iter().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), position| {
+            let position = Vec3::from(*position);
+            (min.min(position), max.max(position))
+        },
+    );
+    let center = (min + max) / 2.0;
+    let radius = positions
+        .iter()
+        .map(|position| center.distance(Vec3::from(*position)))
+        .fold(0.0, f32::max);
+    return Sphere {
+        center: center.into(),
+        radius,
+    };
+}
+
+/// Distance from a point to the surface of a sphere, zero when it is inside.
+fn distance_to_sphere(sphere: &Sphere, point: Vec3) -> f32 {
+    return (point.distance(Vec3::from(sphere.center)) - sphere.radius).max(0.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn children_and_parent_round_trip() {
+        let chunk = ChunkId {
+            face: 3,
+            depth: 2,
+            x: 1,
+            y: 2,
+        };
+        for child in chunk.children() {
+            assert_eq!(child.parent(), Some(chunk));
+            assert_eq!(child.edge_length(), chunk.edge_length() / 2.0);
+        }
+        assert_eq!(ChunkId::root(3).parent(), None);
+    }
+
+    #[test]
+    fn chunk_bounds_contain_every_vertex() {
+        let positions: Vec<[f32; 3]> = vec![
+            [1.0, -0.5, 0.2],
+            [1.1, 0.5, -0.3],
+            [0.9, 0.0, 0.4],
+            [1.0, 0.2, -0.1],
+        ];
+        let mut mesh = Mesh::new(bevy::render::render_resource::PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions.clone());
+
+        let bounds = chunk_bounds(&mesh);
+        for position in positions {
+            assert_eq!(distance_to_sphere(&bounds, Vec3::from(position)), 0.0);
+        }
+        let outside = Vec3::from(bounds.center) + Vec3::X * (bounds.radius + 2.0);
+        assert!((distance_to_sphere(&bounds, outside) - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn leaves_split_close_up_and_merge_past_the_hysteresis() {
+        use crate::planet::terrain::height_faces;
+        use bevy::{ecs::system::CommandQueue, tasks::TaskPool};
+
+        AsyncComputeTaskPool::get_or_init(TaskPool::new);
+        let lod_config = LodConfig {
+            chunk_resolution: 4,
+            max_depth: 1,
+            split_distance: 0.5,
+            merge_hysteresis: 0.4,
+            skirt_depth: 0.0,
+        };
+        let height_maps = Arc::new(height_faces(8, |_| 0.5));
+        let terrain_config = TerrainConfig::default();
+        let mut quadtree = PlanetQuadtree::new(
+            height_maps.clone(),
+            lod_config.clone(),
+            terrain_config.clone(),
+        );
+        let root = ChunkId::root(0);
+        let mut meshes = Assets::<Mesh>::default();
+        let root_mesh = planet_mesh::spawn_chunk(root, &height_maps, &lod_config, &terrain_config);
+        quadtree.insert_mesh(root, root_mesh, &mut meshes);
+
+        let mut world = World::new();
+        let face_entity = world.spawn_empty().id();
+        let mut update = |quadtree: &mut PlanetQuadtree, camera: Option<Vec3>| {
+            let mut queue = CommandQueue::default();
+            let mut commands = Commands::new(&mut queue, &world);
+            match camera {
+                Some(camera_position) => quadtree.update(&mut commands, camera_position),
+                None => quadtree.attach_face(&mut commands, 0, face_entity, Handle::default()),
+            }
+            queue.apply(&mut world);
+        };
+        update(&mut quadtree, None);
+
+        // Out along +X, `split_distances` root chunk widths away from its bounding sphere
+        let bounds = quadtree.meshes[&root].bounds.clone();
+        let camera_at = |split_distances: f32| {
+            let distance = lod_config.split_distance * split_distances * root.edge_length();
+            return Vec3::from(bounds.center) + Vec3::X * (bounds.radius + distance);
+        };
+        let leaves = |quadtree: &PlanetQuadtree| {
+            let mut leaves: Vec<ChunkId> = quadtree.leaves.keys().copied().collect();
+            leaves.sort_by_key(|chunk| (chunk.depth, chunk.y, chunk.x));
+            return leaves;
+        };
+
+        update(&mut quadtree, Some(camera_at(1.2)));
+        assert_eq!(leaves(&quadtree), vec![root]);
+        // The root only splits once the meshes of its children are generated
+        update(&mut quadtree, Some(camera_at(0.8)));
+        assert_eq!(leaves(&quadtree), vec![root]);
+        while !quadtree.pending.is_empty() {
+            std::thread::yield_now();
+            quadtree.poll_tasks(&mut meshes);
+        }
+        update(&mut quadtree, Some(camera_at(0.8)));
+        assert_eq!(leaves(&quadtree), root.children().to_vec());
+        // Backing out past the split distance but within the hysteresis keeps the children
+        for split_distances in [1.05, 1.2, 1.35] {
+            update(&mut quadtree, Some(camera_at(split_distances)));
+            assert_eq!(leaves(&quadtree), root.children().to_vec());
+        }
+        update(&mut quadtree, Some(camera_at(1.45)));
+        assert_eq!(leaves(&quadtree), vec![root]);
+        // Coming back into the hysteresis band does not split again either
+        update(&mut quadtree, Some(camera_at(1.2)));
+        assert_eq!(leaves(&quadtree), vec![root]);
+        assert_eq!(world.entities().len(), 2);
+    }
+}
@@ -0,0 +1,355 @@
+use std::sync::Arc;
+
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    tasks::{AsyncComputeTaskPool, Task},
+};
+
+use super::{
+    craters, cube_map,
+    noise::{self, NoiseSource},
+};
+use crate::config_parser::{ProceduralTerrainConfig, TerrainConfig, TerrainSource};
+
+/// Radius of the sphere the terrain noise is sampled on, in noise grid cells.
+pub const TERRAIN_NOISE_RADIUS: f32 = 256.0;
+
+const BASIN_COLOR: Vec3 = Vec3::new(0.30, 0.14, 0.08);
+const PLATEAU_COLOR: Vec3 = Vec3::new(0.72, 0.42, 0.24);
+const PEAK_COLOR: Vec3 = Vec3::new(0.85, 0.62, 0.45);
+const ICE_COLOR: Vec3 = Vec3::new(0.93, 0.93, 0.96);
+
+/// The height and color cube faces the planet is drawn from, in `FACE_DIRECTIONS` order.
+/// Filled from the authored textures or the procedural generator once `TerrainTasks` finish.
+#[derive(Resource)]
+pub struct TerrainMaps {
+    pub heights: Arc<Vec<Image>>,
+    pub colors: Vec<Handle<Image>>,
+}
+
+/// Terrain faces being built on the async compute pool, one task per face in
+/// `FACE_DIRECTIONS` order.
+pub struct TerrainTasks {
+    faces: Vec<Task<TerrainFace>>,
+    /// `None` when the colors are generated along with the heights.
+    authored_colors: Option<Vec<Handle<Image>>>,
+}
+
+/// One finished face, `color` is only set when the face was generated.
+struct TerrainFace {
+    height: Image,
+    color: Option<Image>,
+}
+
+/// The terrain once every face task is done. Generated colors are still outside of the asset
+/// storage, which only the main thread can add them to.
+pub struct BuiltTerrain {
+    pub heights: Arc<Vec<Image>>,
+    colors: TerrainColors,
+}
+
+enum TerrainColors {
+    Authored(Vec<Handle<Image>>),
+    Generated(Vec<Image>),
+}
+
+/// Every noise layer of the terrain, seeded apart so they do not line up.
+struct TerrainLayers<'a> {
+    config: &'a ProceduralTerrainConfig,
+    continents: noise::ConfiguredNoise,
+    highlands: noise::ConfiguredNoise,
+    crater_fields: noise::ConfiguredNoise,
+    cap_wobble: noise::ConfiguredNoise,
+}
+
+impl<'a> TerrainLayers<'a> {
+    fn new(seed: u64, config: &'a ProceduralTerrainConfig) -> Self {
+        return TerrainLayers {
+            config,
+            continents: noise::from_config(seed, &config.continents),
+            highlands: noise::from_config(seed.wrapping_add(1), &config.highlands),
+            crater_fields: noise::from_config(seed.wrapping_add(2), &config.crater_fields),
+            cap_wobble: noise::from_config(seed.wrapping_add(3), &config.continents),
+        };
+    }
+
+    /// Height in [0, 1] and surface color above a unit direction.
+    fn sample(&self, direction: Vec3) -> (f32, Vec3) {
+        let point = direction * TERRAIN_NOISE_RADIUS;
+
+        let continents = self.continents.sample(point) - self.config.hemisphere_bias * direction.y;
+        let plateau = smoothstep(-0.25, 0.25, continents);
+        let ridges = self.highlands.sample(point) * 0.5 + 0.5;
+        let ridge_height = ridges * (0.3 + 0.7 * plateau);
+        let mut height = 0.2 + 0.4 * plateau + 0.3 * ridge_height;
+
+        // Crater fields scar the old highlands far more than the smoother basins
+        let crater_cell = self.crater_fields.sample(point);
+        let pit = ((-0.3 - crater_cell) / 0.7).clamp(0.0, 1.0);
+        let crater = pit * pit * (0.4 + 0.6 * plateau);
+        height -= self.config.crater_depth * crater;
+
+        let (latitude, _) = cube_map::direction_to_lat_long(direction);
+        let cap_edge = self.config.polar_cap_latitude + self.cap_wobble.sample(point) * 4.0;
+        let ice = smoothstep(cap_edge - 2.0, cap_edge + 2.0, latitude.abs());
+        height = (height + 0.03 * ice).clamp(0.0, 1.0);
+
+        let rock = BASIN_COLOR
+            .lerp(PLATEAU_COLOR, plateau)
+            .lerp(PEAK_COLOR, ridge_height * plateau)
+            * (1.0 - 0.3 * crater);
+        return (height, rock.lerp(ICE_COLOR, ice));
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    return t * t * (3.0 - 2.0 * t);
+}
+
+/// Generates the height and color images of one face of a Mars-like world. Faces are
+/// sampled by direction on the sphere, so they line up across the seams.
+fn generate_face(layers: &TerrainLayers, face: usize) -> (Image, Image) {
+    let size = layers.config.face_size;
+    let mut heights: Vec<u8> = Vec::with_capacity((size * size * 4) as usize);
+    let mut colors: Vec<u8> = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let direction = cube_map::pixel_point(face, x, y, size).normalize();
+            let (height, color) = layers.sample(direction);
+            heights.extend_from_slice(&height.to_ne_bytes());
+            let color = (color.clamp(Vec3::ZERO, Vec3::ONE) * 255.0).round();
+            colors.extend_from_slice(&[color.x as u8, color.y as u8, color.z as u8, 255]);
+        }
+    }
+
+    let face_image = |data: Vec<u8>, format: TextureFormat| {
+        Image::new(
+            Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            format,
+        )
+    };
+    return (
+        face_image(heights, TextureFormat::R32Float),
+        face_image(colors, TextureFormat::Rgba8UnormSrgb),
+    );
+}
+
+/// Starts building the terrain `terrain_config` asks for, from the authored faces or
+/// procedurally from the seed, with one task per face on the async compute pool that also
+/// stamps in the craters of that face. Authored height faces are copied out of `images` up front, so nothing but the finished colors has
+/// to come back to the main thread. Map generation and loading a save both go through here,
+/// so the planet looks the same either way. Nothing of this is cached, procedural faces are
+/// generated again on every load.
+pub fn spawn_terrain_tasks(
+    seed: u64,
+    terrain_config: &TerrainConfig,
+    authored_heights: [&Handle<Image>; 6],
+    authored_colors: [&Handle<Image>; 6],
+    images: &Assets<Image>,
+) -> TerrainTasks {
+    let thread_pool = AsyncComputeTaskPool::get();
+    // Scattering is cheap next to stamping, so every face task stamps the same craters
+    let craters = match terrain_config.craters.count {
+        0 => None,
+        // Offset past the terrain layer seeds so craters do not follow the noise
+        _ => Some(Arc::new(craters::scatter_craters(
+            seed.wrapping_add(4),
+            &terrain_config.craters,
+        ))),
+    };
+    let faces = authored_heights
+        .into_iter()
+        .enumerate()
+        .map(|(face, handle)| {
+            let authored_height = match terrain_config.source {
+                TerrainSource::Textures => Some(
+                    images
+                        .get(handle)
+                        .expect("Height maps are loaded before the terrain is built")
+                        .clone(),
+                ),
+                TerrainSource::Procedural => None,
+            };
+            let procedural = terrain_config.procedural.clone();
+            let crater_config = terrain_config.craters.clone();
+            let craters = craters.clone();
+            return thread_pool.spawn(async move {
+                let (height, color) = match authored_height {
+                    Some(height) => (height, None),
+                    None => {
+                        let (height, color) =
+                            generate_face(&TerrainLayers::new(seed, &procedural), face);
+                        (height, Some(color))
+                    }
+                };
+                let height = match craters {
+                    Some(craters) => {
+                        craters::stamp_craters(face, &height, &craters, &crater_config)
+                    }
+                    None => height,
+                };
+                return TerrainFace { height, color };
+            });
+        })
+        .collect();
+    let authored_colors = match terrain_config.source {
+        TerrainSource::Textures => Some(authored_colors.into_iter().cloned().collect()),
+        TerrainSource::Procedural => None,
+    };
+    return TerrainTasks {
+        faces,
+        authored_colors,
+    };
+}
+
+impl TerrainTasks {
+    /// Waits for every face, craters are already stamped in by then.
+    pub async fn join(self) -> BuiltTerrain {
+        let mut heights: Vec<Image> = Vec::with_capacity(self.faces.len());
+        let mut colors: Vec<Image> = Vec::new();
+        for task in self.faces {
+            let face = task.await;
+            heights.push(face.height);
+            colors.extend(face.color);
+        }
+        return BuiltTerrain {
+            heights: Arc::new(heights),
+            colors: match self.authored_colors {
+                Some(handles) => TerrainColors::Authored(handles),
+                None => TerrainColors::Generated(colors),
+            },
+        };
+    }
+}
+
+impl BuiltTerrain {
+    /// Adds generated colors to the asset storage and hands out the maps to draw with.
+    pub fn into_maps(self, images: &mut Assets<Image>) -> TerrainMaps {
+        let colors = match self.colors {
+            TerrainColors::Authored(handles) => handles,
+            TerrainColors::Generated(colors) => {
+                colors.into_iter().map(|color| images.add(color)).collect()
+            }
+        };
+        return TerrainMaps {
+            heights: self.heights,
+            colors,
+        };
+    }
+}
+
+/// Builds single channel float height faces by sampling `height` in every pixel direction,
+/// for tests that need a planet of a known shape.
+#[cfg(test)]
+pub(super) fn height_faces(size: u32, height: impl Fn(Vec3) -> f32) -> Vec<Image> {
+    return (0..cube_map::FACE_DIRECTIONS.len())
+        .map(|face| {
+            let data = (0..size * size)
+                .flat_map(|i| {
+                    let direction = cube_map::pixel_point(face, i % size, i / size, size);
+                    height(direction.normalize()).to_ne_bytes()
+                })
+                .collect();
+            Image::new(
+                Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                data,
+                TextureFormat::R32Float,
+            )
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_parser::TerrainConfig;
+    use crate::planet::planet_mesh::HeightField;
+    use bevy::tasks::TaskPool;
+
+    fn small_config() -> ProceduralTerrainConfig {
+        return ProceduralTerrainConfig {
+            face_size: 24,
+            ..default()
+        };
+    }
+
+    /// Heights and colors of every face, built the way the loading screen builds them.
+    fn generate_terrain(seed: u64, config: &ProceduralTerrainConfig) -> (Vec<Image>, Vec<Image>) {
+        AsyncComputeTaskPool::get_or_init(TaskPool::new);
+        let terrain_config = TerrainConfig {
+            source: TerrainSource::Procedural,
+            procedural: config.clone(),
+            ..default()
+        };
+        let unloaded = Handle::default();
+        let tasks = spawn_terrain_tasks(
+            seed,
+            &terrain_config,
+            [&unloaded; 6],
+            [&unloaded; 6],
+            &default(),
+        );
+        let terrain = futures_lite::future::block_on(tasks.join());
+        let TerrainColors::Generated(colors) = terrain.colors else {
+            panic!("procedural terrain generates its colors");
+        };
+        return (terrain.heights.to_vec(), colors);
+    }
+
+    #[test]
+    fn generation_is_seeded() {
+        let config = small_config();
+        let (first_heights, first_colors) = generate_terrain(7, &config);
+        let (again_heights, again_colors) = generate_terrain(7, &config);
+        let (other_heights, _) = generate_terrain(8, &config);
+        assert_eq!(first_heights.len(), cube_map::FACE_DIRECTIONS.len());
+        assert_eq!(first_colors.len(), cube_map::FACE_DIRECTIONS.len());
+        for face in 0..first_heights.len() {
+            assert_eq!(first_heights[face].data, again_heights[face].data);
+            assert_eq!(first_colors[face].data, again_colors[face].data);
+        }
+        assert!((0..first_heights.len())
+            .any(|face| first_heights[face].data != other_heights[face].data));
+    }
+
+    #[test]
+    fn terrain_has_relief_and_ice_caps() {
+        let config = small_config();
+        let (heights, colors) = generate_terrain(1337, &config);
+        let height_field = HeightField::new(&heights, &TerrainConfig::default());
+
+        let samples: Vec<f32> = (0..2000)
+            .map(|i| {
+                let angle = i as f32 * 2.399;
+                let y = 1.0 - 2.0 * (i as f32 + 0.5) / 2000.0;
+                let ring = (1.0 - y * y).sqrt();
+                height_field.height(Vec3::new(ring * angle.cos(), y, ring * angle.sin()))
+            })
+            .collect();
+        assert!(samples.iter().all(|height| (0.0..=1.0).contains(height)));
+        let lowest = samples.iter().cloned().fold(f32::MAX, f32::min);
+        let highest = samples.iter().cloned().fold(f32::MIN, f32::max);
+        assert!(highest - lowest > 0.2, "terrain is flat");
+
+        // Poles sit in the middle of the top and bottom faces and are iced over
+        let centre = config.face_size / 2;
+        for face in [2, 3] {
+            let offset = ((centre * config.face_size + centre) * 4) as usize;
+            let pixel = &colors[face].data[offset..offset + 3];
+            assert!(pixel.iter().all(|channel| *channel > 200), "{:?}", pixel);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use bevy::{pbr::ExtendedMaterial, prelude::*};
+use bevy_mod_raycast::prelude::*;
+
+use crate::{camera_system, loading_screen::AppState::InGame, planet};
+
+/// Sent when the cursor moves onto a different province.
+#[derive(Event)]
+pub struct ProvinceHovered(pub Entity);
+
+/// Sent when a province is left clicked.
+#[derive(Event)]
+pub struct ProvinceSelected(pub Entity);
+
+#[derive(Resource, Default)]
+pub struct HoveredProvince(pub Option<Entity>);
+
+#[derive(Resource, Default)]
+pub struct SelectedProvince(pub Option<Entity>);
+
+pub struct ProvincePickingPlugin;
+
+impl Plugin for ProvincePickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ProvinceHovered>()
+            .add_event::<ProvinceSelected>()
+            .init_resource::<HoveredProvince>()
+            .init_resource::<SelectedProvince>()
+            .add_systems(
+                Update,
+                (
+                    hover_province,
+                    select_province,
+                    log_province_events,
+                    update_province_highlight,
+                )
+                    .chain()
+                    .run_if(in_state(InGame)),
+            );
+    }
+}
+
+fn hover_province(
+    cursor_ray: Res<CursorRay>,
+    mut raycast: Raycast,
+    planet_q: Query<&GlobalTransform, With<camera_system::ThirdPersonCameraTarget>>,
+    provinces_query: Query<(Entity, &planet::Province)>,
+    province_map: Res<planet::ProvinceMap>,
+    mut hovered: ResMut<HoveredProvince>,
+    mut hovered_evw: EventWriter<ProvinceHovered>,
+) {
+    let mut hovered_province: Option<Entity> = None;
+    if let Some(cursor_ray) = **cursor_ray {
+        let val: &[(Entity, IntersectionData)] = raycast.cast_ray(cursor_ray, &default());
+        for (entity, intersection_data) in val.iter() {
+            let Ok(planet_transform) = planet_q.get(*entity) else {
+                continue;
+            };
+            let local_intersection_point = planet_transform
+                .compute_matrix()
+                .inverse()
+                .transform_point3(intersection_data.position());
+
+            if let Some(province_id) = province_map.province_at(local_intersection_point) {
+                hovered_province = provinces_query
+                    .iter()
+                    .find(|(_, province)| province.id == province_id)
+                    .map(|(province_entity, _)| province_entity);
+            }
+            break;
+        }
+    }
+
+    if hovered.0 != hovered_province {
+        hovered.0 = hovered_province;
+        if let Some(province_entity) = hovered_province {
+            hovered_evw.send(ProvinceHovered(province_entity));
+        }
+    }
+}
+
+fn select_province(
+    mouse: Res<Input<MouseButton>>,
+    hovered: Res<HoveredProvince>,
+    mut selected: ResMut<SelectedProvince>,
+    mut selected_evw: EventWriter<ProvinceSelected>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    selected.0 = hovered.0;
+    if let Some(province_entity) = hovered.0 {
+        selected_evw.send(ProvinceSelected(province_entity));
+    }
+}
+
+fn log_province_events(
+    mut hovered_evr: EventReader<ProvinceHovered>,
+    mut selected_evr: EventReader<ProvinceSelected>,
+    provinces_query: Query<&planet::Province>,
+    province_graph: Res<planet::ProvinceGraph>,
+) {
+    for ProvinceHovered(entity) in hovered_evr.read() {
+        if let Ok(province) = provinces_query.get(*entity) {
+            debug!("Hovering province with ID: {}", province.id);
+        }
+    }
+    for ProvinceSelected(entity) in selected_evr.read() {
+        if let Ok(province) = provinces_query.get(*entity) {
+            let mut neighbors: Vec<planet::ProvinceId> =
+                province_graph.neighbors_of(province.id).collect();
+            neighbors.sort();
+            let borders: Vec<String> = neighbors
+                .iter()
+                .map(|neighbor| {
+                    let length = province_graph
+                        .border_length(province.id, *neighbor)
+                        .unwrap_or(0);
+                    format!("{} ({} px)", neighbor, length)
+                })
+                .collect();
+            info!(
+                "Selected province with ID: {} bordering {}",
+                province.id,
+                borders.join(", ")
+            );
+        }
+    }
+}
+
+fn update_province_highlight(
+    hovered: Res<HoveredProvince>,
+    selected: Res<SelectedProvince>,
+    provinces_query: Query<&planet::Province>,
+    planet_q: Query<
+        &Handle<ExtendedMaterial<StandardMaterial, planet::PlanetMaterial>>,
+        With<planet::PlanetEntity>,
+    >,
+    mut planet_mats: ResMut<Assets<ExtendedMaterial<StandardMaterial, planet::PlanetMaterial>>>,
+) {
+    if !hovered.is_changed() && !selected.is_changed() {
+        return;
+    }
+    let province_id = |entity: Option<Entity>| {
+        entity
+            .and_then(|entity| provinces_query.get(entity).ok())
+            .map_or(planet::NO_PROVINCE, |province| province.id)
+    };
+    let hovered_province = province_id(hovered.0);
+    let selected_province = province_id(selected.0);
+
+    for material_handle in planet_q.iter() {
+        if let Some(material) = planet_mats.get_mut(material_handle) {
+            material.extension.highlight.hovered_province = hovered_province;
+            material.extension.highlight.selected_province = selected_province;
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use std::{collections::HashSet, fmt, fs, io, path::Path};
+
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use futures_lite::future;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera_system::ThirdPersonCamera,
+    config_parser::EngineConfig,
+    factions::{Faction, Owner},
+    game_assets::{ColorMapAssets, HeightMapAssets},
+    loading_screen::AppState,
+    planet::{self, Province, ProvinceId, ProvinceMap},
+};
+
+pub const SAVE_PATH: &str = "saves/quicksave.ron";
+
+/// Bumped whenever `SaveGame` changes shape, older saves are ignored rather than misread.
+pub const SAVE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub engine_config: EngineConfig,
+    pub factions: Vec<SavedFaction>,
+    pub provinces: Vec<SavedProvince>,
+    pub province_map: SavedProvinceMap,
+    pub camera: Option<SavedCamera>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedFaction {
+    pub name: String,
+    pub color: Color,
+    pub treasury: f64,
+}
+
+/// A row of the province table, `owner` indexes into `SaveGame::factions`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedProvince {
+    pub id: ProvinceId,
+    pub color: [u8; 3],
+    pub owner: Option<usize>,
+}
+
+/// The province map with every face run length encoded as `(province id, run length)` pairs,
+/// which keeps saves small since provinces are large solid regions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedProvinceMap {
+    pub dimensions: u32,
+    pub faces: Vec<Vec<(ProvinceId, u32)>>,
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedCamera {
+    pub rotation: Quat,
+    pub zoom_radius: f32,
+}
+
+/// The terrain of a restored save, built on the async compute pool while the save is loading.
+#[derive(Component)]
+struct RestoreTerrainComponent(Task<planet::BuiltTerrain>);
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Serialize(ron::Error),
+    Deserialize(ron::error::SpannedError),
+    Version(u32),
+    Config(Vec<String>),
+    Provinces(String),
+    ProvinceMap(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            SaveError::Io(error) => write!(f, "could not access save file: {}", error),
+            SaveError::Serialize(error) => write!(f, "could not write save: {}", error),
+            SaveError::Deserialize(error) => write!(f, "could not read save: {}", error),
+            SaveError::Version(version) => write!(
+                f,
+                "save version {} does not match supported version {}",
+                version, SAVE_VERSION
+            ),
+            SaveError::Config(errors) => {
+                write!(f, "engine config is invalid: {}", errors.join("; "))
+            }
+            SaveError::Provinces(problem) => write!(f, "province table is damaged: {}", problem),
+            SaveError::ProvinceMap(problem) => write!(f, "province map is damaged: {}", problem),
+        };
+    }
+}
+
+pub struct SaveGamePlugin;
+
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::LoadingSave), load_saved_game)
+            .add_systems(
+                Update,
+                handle_terrain_tasks.run_if(in_state(AppState::LoadingSave)),
+            )
+            .add_systems(Update, quick_save.run_if(in_state(AppState::InGame)));
+    }
+}
+
+impl SavedProvinceMap {
+    pub fn encode(province_map: &ProvinceMap) -> Self {
+        let faces = province_map
+            .faces
+            .iter()
+            .map(|face| {
+                let mut runs: Vec<(ProvinceId, u32)> = Vec::new();
+                for province_id in face {
+                    match runs.last_mut() {
+                        Some((run_id, run_length)) if run_id == province_id => *run_length += 1,
+                        _ => runs.push((*province_id, 1)),
+                    }
+                }
+                runs
+            })
+            .collect();
+        return SavedProvinceMap {
+            dimensions: province_map.dimensions,
+            faces,
+        };
+    }
+
+    /// Expands the runs back into a province map, checking that every face is complete and
+    /// only names provinces below `province_count`, so a damaged save cannot index out of
+    /// bounds later on.
+    pub fn decode(&self, province_count: usize) -> Result<ProvinceMap, SaveError> {
+        let face_length = (self.dimensions as usize).checked_mul(self.dimensions as usize);
+        let mut faces: Vec<Vec<ProvinceId>> = Vec::with_capacity(self.faces.len());
+        for (face, runs) in self.faces.iter().enumerate() {
+            // Lengths are summed before expanding so a bogus run cannot allocate gigabytes
+            let length = runs.iter().try_fold(0usize, |length, (_, run_length)| {
+                length.checked_add(*run_length as usize)
+            });
+            if length.is_none() || length != face_length {
+                return Err(SaveError::ProvinceMap(format!(
+                    "face {} does not cover {}x{} pixels",
+                    face, self.dimensions, self.dimensions
+                )));
+            }
+            faces.push(
+                runs.iter()
+                    .flat_map(|(province_id, run_length)| {
+                        std::iter::repeat_n(*province_id, *run_length as usize)
+                    })
+                    .collect(),
+            );
+        }
+        let province_map = ProvinceMap {
+            dimensions: self.dimensions,
+            faces,
+        };
+        province_map
+            .validate(province_count)
+            .map_err(SaveError::ProvinceMap)?;
+        return Ok(province_map);
+    }
+}
+
+pub fn write_save(path: &Path, save: &SaveGame) -> Result<(), SaveError> {
+    let serialized = ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())
+        .map_err(SaveError::Serialize)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(SaveError::Io)?;
+    }
+    return fs::write(path, serialized).map_err(SaveError::Io);
+}
+
+pub fn read_save(path: &Path) -> Result<SaveGame, SaveError> {
+    let serialized = fs::read_to_string(path).map_err(SaveError::Io)?;
+    let save: SaveGame = ron::from_str(&serialized).map_err(SaveError::Deserialize)?;
+    if save.version != SAVE_VERSION {
+        return Err(SaveError::Version(save.version));
+    }
+    return Ok(save);
+}
+
+fn quick_save(
+    input: Res<Input<KeyCode>>,
+    engine_config: Res<EngineConfig>,
+    province_map: Res<ProvinceMap>,
+    provinces_query: Query<(&Province, Option<&Owner>)>,
+    factions_query: Query<(Entity, &Faction)>,
+    camera_query: Query<(&ThirdPersonCamera, &Transform)>,
+) {
+    if !input.just_pressed(KeyCode::F5) {
+        return;
+    }
+
+    let mut faction_entities: Vec<Entity> = Vec::new();
+    let mut factions: Vec<SavedFaction> = Vec::new();
+    for (entity, faction) in factions_query.iter() {
+        faction_entities.push(entity);
+        factions.push(SavedFaction {
+            name: faction.name.clone(),
+            color: faction.color,
+            treasury: faction.treasury,
+        });
+    }
+
+    let mut provinces: Vec<SavedProvince> = provinces_query
+        .iter()
+        .map(|(province, owner)| SavedProvince {
+            id: province.id,
+            color: province.color,
+            owner: owner.and_then(|owner| {
+                faction_entities
+                    .iter()
+                    .position(|entity| *entity == owner.0)
+            }),
+        })
+        .collect();
+    provinces.sort_by_key(|province| province.id);
+
+    let camera = camera_query
+        .get_single()
+        .ok()
+        .map(|(camera, transform)| SavedCamera {
+            rotation: transform.rotation,
+            zoom_radius: camera.zoom.radius(),
+        });
+
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        engine_config: engine_config.clone(),
+        factions,
+        provinces,
+        province_map: SavedProvinceMap::encode(&province_map),
+        camera,
+    };
+    match write_save(Path::new(SAVE_PATH), &save) {
+        Ok(()) => info!("Saved game to {}", SAVE_PATH),
+        Err(error) => error!("Could not save game to {}: {}", SAVE_PATH, error),
+    }
+}
+
+fn load_saved_game(
+    mut commands: Commands,
+    mut state: ResMut<NextState<AppState>>,
+    height_assets: Res<HeightMapAssets>,
+    color_assets: Res<ColorMapAssets>,
+    images: Res<Assets<Image>>,
+) {
+    let path = Path::new(SAVE_PATH);
+    if !path.exists() {
+        state.set(AppState::GeneratingMaps);
+        return;
+    }
+    let save = match read_save(path) {
+        Ok(save) => save,
+        Err(error) => {
+            warn!("Ignoring save {}: {}", SAVE_PATH, error);
+            state.set(AppState::GeneratingMaps);
+            return;
+        }
+    };
+
+    if let Err(error) = restore_save(
+        save,
+        &mut commands,
+        height_assets.faces(),
+        color_assets.faces(),
+        &images,
+    ) {
+        warn!("Ignoring save {}: {}", SAVE_PATH, error);
+        state.set(AppState::GeneratingMaps);
+        return;
+    }
+    info!("Loaded game from {}", SAVE_PATH);
+}
+
+/// Hands the terrain of a restored save over once it is built, the rest of the save is in
+/// place by then.
+fn handle_terrain_tasks(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut RestoreTerrainComponent)>,
+    mut state: ResMut<NextState<AppState>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for (entity, mut task_component) in tasks.iter_mut() {
+        if let Some(terrain) = future::block_on(future::poll_once(&mut task_component.0)) {
+            commands.insert_resource(terrain.into_maps(&mut images));
+            commands.entity(entity).despawn();
+            state.set(AppState::GeneratingMeshes);
+        }
+    }
+}
+
+/// Spawns the factions and provinces of a save and inserts everything mesh generation needs.
+/// The terrain the save was made with is rebuilt on the async compute pool, since map
+/// generation is skipped, and `handle_terrain_tasks` picks it up. Nothing is spawned when the
+/// save turns out to be damaged or was edited into an invalid config.
+fn restore_save(
+    save: SaveGame,
+    commands: &mut Commands,
+    height_maps: [&Handle<Image>; 6],
+    color_maps: [&Handle<Image>; 6],
+    images: &Assets<Image>,
+) -> Result<(), SaveError> {
+    save.engine_config.validate().map_err(SaveError::Config)?;
+    check_provinces(&save.provinces)?;
+    let province_map = save.province_map.decode(save.provinces.len())?;
+    let province_colors: Vec<image::Rgb<u8>> = save
+        .provinces
+        .iter()
+        .map(|province| image::Rgb(province.color))
+        .collect();
+    let (border_images, province_graph, province_geometry) =
+        planet::derive_province_data(&province_map, &province_colors);
+
+    let faction_entities: Vec<Entity> = save
+        .factions
+        .iter()
+        .map(|faction| {
+            commands
+                .spawn(Faction {
+                    name: faction.name.clone(),
+                    color: faction.color,
+                    treasury: faction.treasury,
+                })
+                .id()
+        })
+        .collect();
+    for (province, geometry) in save.provinces.iter().zip(province_geometry) {
+        let mut province_entity = commands.spawn((
+            Province {
+                id: province.id,
+                color: province.color,
+            },
+            geometry,
+        ));
+        if let Some(owner) = province.owner.and_then(|owner| faction_entities.get(owner)) {
+            province_entity.insert(Owner(*owner));
+        }
+    }
+
+    let terrain_tasks = planet::spawn_terrain_tasks(
+        save.engine_config.seed,
+        &save.engine_config.terrain,
+        height_maps,
+        color_maps,
+        images,
+    );
+    commands.spawn(RestoreTerrainComponent(
+        AsyncComputeTaskPool::get().spawn(terrain_tasks.join()),
+    ));
+    commands.insert_resource(save.engine_config);
+    commands.insert_resource(province_map);
+    commands.insert_resource(border_images);
+    commands.insert_resource(province_graph);
+    if let Some(camera) = save.camera {
+        commands.insert_resource(camera);
+    }
+    return Ok(());
+}
+
+/// Province ids index the province map and the derived province data, so the table has to
+/// hold ids 0..n in order, and colors have to be unique to tell provinces apart.
+fn check_provinces(provinces: &[SavedProvince]) -> Result<(), SaveError> {
+    let mut colors: HashSet<[u8; 3]> = HashSet::new();
+    for (index, province) in provinces.iter().enumerate() {
+        if province.id as usize != index {
+            return Err(SaveError::Provinces(format!(
+                "row {} holds province {}",
+                index, province.id
+            )));
+        }
+        if !colors.insert(province.color) {
+            return Err(SaveError::Provinces(format!(
+                "province {} repeats the color {:?}",
+                province.id, province.color
+            )));
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_parser::TerrainSource;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn sample_save() -> SaveGame {
+        let province_map = ProvinceMap {
+            dimensions: 2,
+            faces: vec![
+                vec![0, 0, 1, 1],
+                vec![1, 1, 1, 1],
+                vec![2, 0, 2, 0],
+                vec![2, 2, 2, 2],
+                vec![0, 1, 2, 0],
+                vec![1, 1, 2, 2],
+            ],
+        };
+        return SaveGame {
+            version: SAVE_VERSION,
+            engine_config: EngineConfig {
+                map_dimensions: 4,
+                num_provinces: 3,
+                seed: 42,
+                ..default()
+            },
+            factions: vec![SavedFaction {
+                name: "Olympus Compact".to_owned(),
+                color: Color::rgb(0.8, 0.2, 0.1),
+                treasury: 1250.5,
+            }],
+            provinces: vec![
+                SavedProvince {
+                    id: 0,
+                    color: [10, 20, 30],
+                    owner: Some(0),
+                },
+                SavedProvince {
+                    id: 1,
+                    color: [40, 50, 60],
+                    owner: None,
+                },
+                SavedProvince {
+                    id: 2,
+                    color: [70, 80, 90],
+                    owner: Some(0),
+                },
+            ],
+            province_map: SavedProvinceMap::encode(&province_map),
+            camera: Some(SavedCamera {
+                rotation: Quat::from_rotation_y(0.5),
+                zoom_radius: 3.25,
+            }),
+        };
+    }
+
+    #[test]
+    fn province_map_round_trip() {
+        let save = sample_save();
+        let decoded = save.province_map.decode(save.provinces.len()).unwrap();
+        assert_eq!(SavedProvinceMap::encode(&decoded), save.province_map);
+        assert_eq!(decoded.faces[2], vec![2, 0, 2, 0]);
+        assert_eq!(save.province_map.faces[1], vec![(1, 4)]);
+    }
+
+    #[test]
+    fn damaged_province_maps_are_rejected() {
+        let save = sample_save();
+        let decode = |province_map: &SavedProvinceMap| province_map.decode(save.provinces.len());
+
+        let mut truncated = save.province_map.clone();
+        truncated.faces[3] = vec![(2, 3)];
+        assert!(matches!(decode(&truncated), Err(SaveError::ProvinceMap(_))));
+
+        let mut missing_face = save.province_map.clone();
+        missing_face.faces.pop();
+        assert!(matches!(
+            decode(&missing_face),
+            Err(SaveError::ProvinceMap(_))
+        ));
+
+        let mut unknown_province = save.province_map.clone();
+        unknown_province.faces[1] = vec![(3, 4)];
+        assert!(matches!(
+            decode(&unknown_province),
+            Err(SaveError::ProvinceMap(_))
+        ));
+
+        let mut oversized = save.province_map.clone();
+        oversized.faces[0] = vec![(0, u32::MAX), (0, u32::MAX), (0, 6)];
+        assert!(matches!(decode(&oversized), Err(SaveError::ProvinceMap(_))));
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let save = sample_save();
+        let path = std::env::temp_dir().join(format!("red_sand_save_{}.ron", std::process::id()));
+        write_save(&path, &save).unwrap();
+        let loaded = read_save(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, save);
+    }
+
+    #[test]
+    fn load_rejects_other_versions() {
+        let mut save = sample_save();
+        save.version = SAVE_VERSION + 1;
+        let path =
+            std::env::temp_dir().join(format!("red_sand_old_save_{}.ron", std::process::id()));
+        write_save(&path, &save).unwrap();
+        let loaded = read_save(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(SaveError::Version(_))));
+    }
+
+    #[test]
+    fn restoring_a_save_rebuilds_the_terrain() {
+        use bevy::tasks::{AsyncComputeTaskPool, TaskPool};
+
+        // Procedural faces are generated on the pool the app normally sets up
+        AsyncComputeTaskPool::get_or_init(TaskPool::new);
+        let mut save = sample_save();
+        save.engine_config.terrain.source = TerrainSource::Procedural;
+        save.engine_config.terrain.procedural.face_size = 8;
+        save.engine_config.terrain.craters.count = 3;
+
+        let mut world = World::new();
+        world.init_resource::<Assets<Image>>();
+        world.init_resource::<NextState<AppState>>();
+        let restored = save.clone();
+        world.run_system_once(move |mut commands: Commands, images: Res<Assets<Image>>| {
+            // Procedural terrain never touches the authored faces
+            let unloaded = Handle::default();
+            restore_save(
+                restored.clone(),
+                &mut commands,
+                [&unloaded; 6],
+                [&unloaded; 6],
+                &images,
+            )
+            .unwrap();
+        });
+        while !world.contains_resource::<planet::TerrainMaps>() {
+            world.run_system_once(handle_terrain_tasks);
+        }
+
+        assert_eq!(
+            world.resource::<NextState<AppState>>().0,
+            Some(AppState::GeneratingMeshes)
+        );
+        let terrain_maps = world.resource::<planet::TerrainMaps>();
+        assert_eq!(terrain_maps.heights.len(), 6);
+        assert_eq!(terrain_maps.colors.len(), 6);
+        assert!(terrain_maps
+            .colors
+            .iter()
+            .all(|color| world.resource::<Assets<Image>>().contains(color)));
+        assert_eq!(
+            SavedProvinceMap::encode(world.resource::<ProvinceMap>()),
+            save.province_map
+        );
+        assert_eq!(world.resource::<EngineConfig>(), &save.engine_config);
+        assert_eq!(world.query::<&Province>().iter(&world).count(), 3);
+        assert_eq!(world.query::<&Owner>().iter(&world).count(), 2);
+    }
+
+    #[test]
+    fn invalid_saves_are_not_restored() {
+        let restore = |save: SaveGame| {
+            let mut world = World::new();
+            world.init_resource::<Assets<Image>>();
+            let result =
+                world.run_system_once(move |mut commands: Commands, images: Res<Assets<Image>>| {
+                    let unloaded = Handle::default();
+                    return restore_save(
+                        save.clone(),
+                        &mut commands,
+                        [&unloaded; 6],
+                        [&unloaded; 6],
+                        &images,
+                    );
+                });
+            assert_eq!(world.query::<&Province>().iter(&world).count(), 0);
+            assert!(!world.contains_resource::<EngineConfig>());
+            return result;
+        };
+
+        let mut invalid_config = sample_save();
+        invalid_config.engine_config.lod.chunk_resolution = 1;
+        assert!(matches!(restore(invalid_config), Err(SaveError::Config(_))));
+
+        let mut shuffled = sample_save();
+        shuffled.provinces.swap(0, 2);
+        assert!(matches!(restore(shuffled), Err(SaveError::Provinces(_))));
+
+        let mut repeated_color = sample_save();
+        repeated_color.provinces[2].color = repeated_color.provinces[0].color;
+        assert!(matches!(
+            restore(repeated_color),
+            Err(SaveError::Provinces(_))
+        ));
+    }
+}
@@ -16,7 +16,6 @@ pub fn setup(mut commands: Commands, image_assets: Res<game_assets::ImageAssets>
 
     commands.insert_resource(skybox::Cubemap {
         is_loaded: false,
-        index: 0,
         image_handle: image_assets.skybox_texture.clone(),
     });
 }
@@ -11,7 +11,6 @@ use std::f32::consts::PI;
 #[derive(Resource)]
 pub struct Cubemap {
     pub is_loaded: bool,
-    pub index: usize,
     pub image_handle: Handle<Image>,
 }
 
@@ -33,7 +32,6 @@ pub fn build_skybox(mut commands: Commands, skybox_handler: Res<ImageAssets>) {
 
     commands.insert_resource(Cubemap {
         is_loaded: false,
-        index: 0,
         image_handle: skybox_handler.skybox_texture.clone(),
     });
 }